@@ -0,0 +1,96 @@
+//! Timings for the spatial index on maps much larger than the game uses: `cargo bench`
+
+#[allow(dead_code)]
+#[path = "../src/position.rs"]
+mod position;
+#[allow(dead_code)]
+#[path = "../src/spatial.rs"]
+mod spatial;
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use bracket_lib::prelude::{Point, RandomNumberGenerator, Rect};
+use hecs::{Entity, World};
+
+use position::Position;
+use spatial::SpatialIndex;
+
+const WIDTH: usize = 1000;
+const HEIGHT: usize = 1000;
+const ENTITIES: usize = 20_000;
+const ROUNDS: u32 = 100;
+
+fn bench(name: &str, mut f: impl FnMut()) {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        f();
+    }
+    let per_round = start.elapsed() / ROUNDS;
+    println!("{name:<40} {per_round:>12.3?}");
+}
+
+fn random_position(rng: &mut RandomNumberGenerator) -> Position {
+    Position::new(rng.range(0, WIDTH as i32), rng.range(0, HEIGHT as i32))
+}
+
+/// A random step to a neighbouring tile, staying on the map
+fn step(rng: &mut RandomNumberGenerator, pos: Position) -> Position {
+    let dst = Point::from(pos) + Point::new(rng.range(-1, 2), rng.range(-1, 2));
+    Position::new(
+        dst.x.clamp(0, WIDTH as i32 - 1),
+        dst.y.clamp(0, HEIGHT as i32 - 1),
+    )
+}
+
+fn main() {
+    let mut rng = RandomNumberGenerator::seeded(0);
+    let mut world = World::new();
+    let mut entities: Vec<(Entity, Position, bool)> = (0..ENTITIES)
+        .map(|i| (world.spawn(()), random_position(&mut rng), i % 2 == 0))
+        .collect();
+
+    let mut index = SpatialIndex::new(WIDTH, HEIGHT);
+    println!("{ENTITIES} entities on a {WIDTH}x{HEIGHT} map, {ROUNDS} rounds each");
+
+    // what the game did before the index: clear everything and fill it again every turn
+    bench("rebuild from scratch", || {
+        index.rebuild(entities.iter().copied());
+    });
+
+    bench("move every entity one step", || {
+        for (e, pos, _) in &mut entities {
+            let dst = step(&mut rng, *pos);
+            index.move_to(*e, dst);
+            *pos = dst;
+        }
+    });
+
+    bench("move 100 entities one step", || {
+        for (e, pos, _) in entities.iter_mut().take(100) {
+            let dst = step(&mut rng, *pos);
+            index.move_to(*e, dst);
+            *pos = dst;
+        }
+    });
+
+    bench("1000 blocked tile lookups", || {
+        for _ in 0..1000 {
+            black_box(index.is_blocked(random_position(&mut rng)));
+        }
+    });
+
+    bench("1000 radius 8 queries", || {
+        for _ in 0..1000 {
+            black_box(index.in_radius(random_position(&mut rng), 8.0).count());
+        }
+    });
+
+    bench("1000 20x20 rectangle queries", || {
+        for _ in 0..1000 {
+            let pos = random_position(&mut rng);
+            let rect = Rect::with_size(pos.x as i32, pos.y as i32, 20, 20);
+            black_box(index.in_rect(rect).count());
+        }
+    });
+}
@@ -0,0 +1,625 @@
+use bracket_lib::prelude::*;
+use hecs::Entity;
+
+use crate::{
+    animation::{Animation, AnimationKind, Easing, FrameCallback},
+    combat::{CombatStats, SufferDamage},
+    companion::{Companion, Summoned},
+    damage::{Damage, DamageType},
+    formula::Dice,
+    map::Map,
+    monster::Monster,
+    noise::{Noise, COMBAT_LOUDNESS},
+    position::Position,
+    spawn,
+    status::Confused,
+    stealth::Awareness,
+    Name, Phase, State,
+};
+
+/// Turns it takes to get back one point of mana
+const MANA_REGEN_INTERVAL: u32 = 3;
+/// Half the opening angle of a cone, in degrees
+const CONE_HALF_ANGLE: f32 = 30.0;
+
+/// Which tiles an ability reaches
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Targeting {
+    /// Only the caster
+    Caster,
+    /// The target tile, when within `range`
+    Single { range: i32 },
+    /// Every tile on a ray towards the target, up to a wall
+    Line { range: i32 },
+    /// Tiles in view spreading out from the caster towards the target
+    Cone { range: i32 },
+    /// Tiles in view within `radius` of a target within `range`
+    Burst { range: i32, radius: i32 },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Effect {
+    Damage {
+        dice: Dice,
+        kind: DamageType,
+    },
+    Heal {
+        dice: Dice,
+    },
+    Confuse {
+        turns: i32,
+    },
+    /// Call up a wolf fighting on the caster's side for a while
+    Summon {
+        turns: i32,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Ability {
+    pub name: &'static str,
+    pub targeting: Targeting,
+    pub effect: Effect,
+    /// Mana spent on every use
+    pub cost: i32,
+    /// Turns to wait between two uses
+    pub cooldown: u32,
+    /// Turns left until it can be used again
+    pub ready_in: u32,
+    pub glyph: char,
+    pub color: RGB,
+}
+
+impl Ability {
+    fn new(
+        name: &'static str,
+        targeting: Targeting,
+        effect: Effect,
+        glyph: char,
+        color: RGB,
+    ) -> Self {
+        Self {
+            name,
+            targeting,
+            effect,
+            cost: 0,
+            cooldown: 0,
+            ready_in: 0,
+            glyph,
+            color,
+        }
+    }
+
+    fn with_cost(mut self, cost: i32) -> Self {
+        self.cost = cost;
+        self
+    }
+
+    fn with_cooldown(mut self, cooldown: u32) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    pub fn firebolt() -> Self {
+        let effect = Effect::Damage {
+            dice: Dice::new(2, 6, 0),
+            kind: DamageType::Fire,
+        };
+        Self::new(
+            "Firebolt",
+            Targeting::Single { range: 8 },
+            effect,
+            '*',
+            RGB::named(ORANGE),
+        )
+        .with_cost(5)
+    }
+
+    pub fn frost_ray() -> Self {
+        let effect = Effect::Damage {
+            dice: Dice::new(2, 4, 0),
+            kind: DamageType::Cold,
+        };
+        Self::new(
+            "Frost ray",
+            Targeting::Line { range: 6 },
+            effect,
+            '*',
+            RGB::named(CYAN),
+        )
+        .with_cost(6)
+    }
+
+    pub fn confusion() -> Self {
+        let effect = Effect::Confuse { turns: 4 };
+        Self::new(
+            "Confusion",
+            Targeting::Cone { range: 4 },
+            effect,
+            '?',
+            RGB::named(MAGENTA),
+        )
+        .with_cooldown(15)
+    }
+
+    pub fn fireball() -> Self {
+        let effect = Effect::Damage {
+            dice: Dice::new(3, 6, 0),
+            kind: DamageType::Fire,
+        };
+        let targeting = Targeting::Burst {
+            range: 8,
+            radius: 2,
+        };
+        Self::new("Fireball", targeting, effect, '*', RGB::named(RED))
+            .with_cost(12)
+            .with_cooldown(5)
+    }
+
+    pub fn heal() -> Self {
+        let effect = Effect::Heal {
+            dice: Dice::new(2, 8, 0),
+        };
+        Self::new("Heal", Targeting::Caster, effect, '+', RGB::named(GREEN))
+            .with_cost(8)
+            .with_cooldown(10)
+    }
+
+    pub fn summon_wolf() -> Self {
+        let effect = Effect::Summon { turns: 50 };
+        Self::new(
+            "Summon wolf",
+            Targeting::Caster,
+            effect,
+            '&',
+            RGB::named(LIGHT_BLUE),
+        )
+        .with_cost(10)
+        .with_cooldown(60)
+    }
+
+    /// Whether it is off cooldown and affordable
+    pub fn ready(&self, mana: Option<&Mana>) -> bool {
+        self.ready_in == 0 && (self.cost == 0 || mana.is_some_and(|m| m.current >= self.cost))
+    }
+}
+
+/// Abilities an entity can use, the player casts them with the number keys in this order
+#[derive(Debug, Clone, Default)]
+pub struct Abilities(pub Vec<Ability>);
+
+#[derive(Debug, Clone, Copy)]
+pub struct Mana {
+    pub max: i32,
+    pub current: i32,
+    counter: u32,
+}
+
+impl Mana {
+    pub fn new(max: i32) -> Self {
+        Self {
+            max,
+            current: max,
+            counter: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct WantsToCast {
+    /// Index into the caster's `Abilities`
+    pub ability: usize,
+    pub target: Position,
+}
+
+fn is_wall(map: &Map, p: Point) -> bool {
+    !map.in_bounds(p) || map.is_opaque(map.to_idx(p))
+}
+
+/// Angle in degrees between two directions
+fn angle_between(a: Point, b: Point) -> f32 {
+    let dot = (a.x * b.x + a.y * b.y) as f32;
+    let len = |p: Point| ((p.x * p.x + p.y * p.y) as f32).sqrt();
+    (dot / (len(a) * len(b)))
+        .clamp(-1.0, 1.0)
+        .acos()
+        .to_degrees()
+}
+
+/// Tiles reached by an ability used from `origin` towards `target`, empty when out of reach
+pub fn affected_tiles(
+    map: &Map,
+    targeting: Targeting,
+    origin: Position,
+    target: Position,
+) -> Vec<Position> {
+    let from = Point::from(origin);
+    let in_range =
+        |p: Point, range: i32| DistanceAlg::Pythagoras.distance2d(from, p) <= range as f32;
+    let in_view = |center: Point, range: i32| {
+        field_of_view(center, range, map)
+            .into_iter()
+            .filter(|&p| map.in_bounds(p))
+    };
+
+    let tiles: Vec<Point> = match targeting {
+        Targeting::Caster => vec![from],
+        Targeting::Single { range } if in_range(target.into(), range) => vec![target.into()],
+        Targeting::Line { range } if target != origin => {
+            // carry on past the target up to the full range
+            let dir = Point::from(target) - from;
+            let len = dir.x.abs().max(dir.y.abs());
+            let end = from + Point::new(dir.x * range / len, dir.y * range / len);
+            line2d(LineAlg::Bresenham, from, end)
+                .into_iter()
+                .skip(1)
+                .take_while(|&p| !is_wall(map, p) && in_range(p, range))
+                .collect()
+        }
+        Targeting::Cone { range } if target != origin => {
+            let dir = Point::from(target) - from;
+            in_view(from, range)
+                .filter(|&p| p != from && in_range(p, range))
+                .filter(|&p| angle_between(dir, p - from) <= CONE_HALF_ANGLE)
+                .collect()
+        }
+        Targeting::Burst { range, radius }
+            if in_range(target.into(), range) && !is_wall(map, target.into()) =>
+        {
+            let center = Point::from(target);
+            in_view(center, radius)
+                .filter(|&p| DistanceAlg::Pythagoras.distance2d(center, p) <= radius as f32)
+                .collect()
+        }
+        _ => Vec::new(),
+    };
+    tiles
+        .into_iter()
+        .filter_map(|p| Position::try_from(p).ok())
+        .collect()
+}
+
+/// Pick an ability for a monster fighting the player, if one is worth using
+pub fn choose(
+    abilities: &Abilities,
+    mana: Option<&Mana>,
+    stats: &CombatStats,
+    map: &Map,
+    pos: Position,
+    player_pos: Position,
+) -> Option<WantsToCast> {
+    abilities
+        .0
+        .iter()
+        .enumerate()
+        .filter(|(_, ability)| ability.ready(mana))
+        .find_map(|(index, ability)| {
+            let target = match ability.effect {
+                Effect::Heal { .. } if stats.hp * 2 < stats.max_hp => pos,
+                Effect::Heal { .. } => return None,
+                _ => player_pos,
+            };
+            affected_tiles(map, ability.targeting, pos, target)
+                .contains(&target)
+                .then_some(WantsToCast {
+                    ability: index,
+                    target,
+                })
+        })
+}
+
+/// Use the abilities entities want to cast
+pub fn run(gs: &mut State) {
+    let casts: Vec<(Entity, WantsToCast)> = gs
+        .world
+        .query_mut::<&WantsToCast>()
+        .into_iter()
+        .map(|(e, cast)| (e, *cast))
+        .collect();
+    for (caster, cast) in casts {
+        _ = gs.world.remove_one::<WantsToCast>(caster);
+        cast_ability(gs, caster, cast);
+    }
+}
+
+fn cast_ability(gs: &mut State, caster: Entity, cast: WantsToCast) {
+    type Q<'w> = (
+        &'w Position,
+        &'w Name,
+        &'w mut Abilities,
+        Option<&'w mut Mana>,
+    );
+    let Ok((pos, name, abilities, mana)) = gs.world.query_one_mut::<Q>(caster) else {
+        return;
+    };
+    let Some(ability) = abilities.0.get_mut(cast.ability) else {
+        return;
+    };
+    if !ability.ready(mana.as_deref()) {
+        return;
+    }
+    let origin = *pos;
+    let tiles = affected_tiles(&gs.map, ability.targeting, origin, cast.target);
+    if tiles.is_empty() {
+        return;
+    }
+    if let Some(mana) = mana {
+        mana.current -= ability.cost;
+    }
+    // `recover` counts this turn down too, at the end of the monsters' turn
+    ability.ready_in = ability.cooldown + 1;
+    let ability = ability.clone();
+    gs.msg_log.push(format!("{name} casts {}.", ability.name));
+
+    let delay = animate(gs, &ability, origin, cast.target, &tiles);
+    if let Effect::Summon { turns } = ability.effect {
+        summon(gs, caster, origin, turns);
+        return;
+    }
+    if matches!(ability.effect, Effect::Damage { .. }) {
+        gs.noises.push(Noise::new(origin, COMBAT_LOUDNESS));
+    }
+
+    let targets: Vec<(Entity, Position)> = tiles
+        .iter()
+        .flat_map(|&pos| gs.map.index.at(pos).iter().map(move |&e| (e, pos)))
+        .filter(|&(e, _)| gs.world.satisfies::<&CombatStats>(e).unwrap_or(false))
+        .filter(|&(e, _)| e != caster || ability.targeting == Targeting::Caster)
+        .collect();
+    for (target, pos) in targets {
+        let target_name = gs
+            .world
+            .get::<&Name>(target)
+            .map(|name| name.to_string())
+            .unwrap_or_default();
+        match ability.effect {
+            Effect::Damage { dice, kind } => {
+                let amount = dice.roll(&mut gs.rng);
+                gs.msg_log.push(format!(
+                    "The {} hits {target_name} for {amount} {kind}",
+                    ability.name
+                ));
+                gs.animations.add(
+                    Animation::floating_text(pos, amount, RGB::named(WHITE)).with_delay(delay),
+                );
+                let damage = Damage::new(amount, kind).by(caster);
+                SufferDamage::add_damage(&mut gs.world, target, damage);
+            }
+            Effect::Heal { dice } => {
+                let Ok(stats) = gs.world.query_one_mut::<&mut CombatStats>(target) else {
+                    continue;
+                };
+                let amount = dice.roll(&mut gs.rng).min(stats.max_hp - stats.hp);
+                stats.hp += amount;
+                gs.msg_log
+                    .push(format!("{target_name} is healed for {amount}."));
+                gs.animations.add(
+                    Animation::floating_text(pos, format!("+{amount}"), RGB::named(GREEN))
+                        .with_delay(delay),
+                );
+            }
+            Effect::Confuse { turns } => {
+                _ = gs.world.insert_one(target, Confused { turns });
+                gs.msg_log.push(format!("{target_name} is confused."));
+            }
+            Effect::Summon { .. } => {}
+        }
+    }
+}
+
+fn summon(gs: &mut State, caster: Entity, origin: Position, turns: i32) {
+    let Some((idx, _)) = gs
+        .map
+        .get_available_exits(gs.map.to_idx(origin))
+        .first()
+        .copied()
+    else {
+        gs.msg_log
+            .push("There is no room for anything to appear.".into());
+        return;
+    };
+    let pos = gs.map.to_pos(idx);
+    let wolf = spawn::wolf(&mut gs.world, pos);
+    _ = gs.world.insert_one(wolf, Summoned { turns });
+    if caster == gs.player {
+        _ = gs.world.insert_one(wolf, Companion::default());
+    } else {
+        _ = gs.world.insert(wolf, (Monster, Awareness::alert()));
+    }
+    gs.place(wolf);
+    gs.msg_log.push("A wolf appears.".into());
+}
+
+/// Show the ability going off, returns the frames until it reaches its targets
+fn animate(
+    gs: &mut State,
+    ability: &Ability,
+    origin: Position,
+    target: Position,
+    tiles: &[Position],
+) -> u32 {
+    let path_to = |end: Position| -> Vec<Position> {
+        line2d(LineAlg::Bresenham, origin.into(), end.into())
+            .into_iter()
+            .skip(1)
+            .filter_map(|p| Position::try_from(p).ok())
+            .collect()
+    };
+    let glyph = |pos: Position| AnimationKind::Glyph {
+        pos,
+        glyph: to_cp437(ability.glyph),
+        color: ability.color,
+    };
+
+    match ability.targeting {
+        Targeting::Caster => {
+            let (glyph, color) = (to_cp437(ability.glyph), ability.color);
+            let pulse: FrameCallback = Box::new(move |ctx: &mut BTerm, offset: Point, t: f32| {
+                // brightest halfway through, fading in and out again
+                let p = Point::from(origin) + offset;
+                let brightness = 1.0 - (2.0 * t - 1.0).abs();
+                ctx.set(p.x, p.y, color * brightness, BLACK, glyph);
+            });
+            gs.animations
+                .add(Animation::custom(6, pulse).with_easing(Easing::EaseInOut));
+            0
+        }
+        Targeting::Single { .. } | Targeting::Line { .. } => {
+            let path = path_to(*tiles.last().unwrap_or(&target));
+            let delay = path.len() as u32 * 2;
+            gs.animations
+                .add(Animation::projectile(path, ability.glyph, ability.color));
+            delay
+        }
+        Targeting::Cone { .. } => {
+            for &pos in tiles {
+                let distance = DistanceAlg::Chebyshev.distance2d(origin.into(), pos.into());
+                gs.animations
+                    .add(Animation::new(glyph(pos), 4).with_delay(distance as u32));
+            }
+            4
+        }
+        Targeting::Burst { radius, .. } => {
+            let path = path_to(target);
+            let delay = path.len() as u32 * 2;
+            gs.animations
+                .add(Animation::projectile(path, ability.glyph, ability.color));
+            gs.animations
+                .add(Animation::explosion(target, radius as f32, ability.color).with_delay(delay));
+            delay
+        }
+    }
+}
+
+/// Count down cooldowns and slowly restore mana
+pub fn recover(gs: &mut State) {
+    for (_, abilities) in gs.world.query_mut::<&mut Abilities>() {
+        for ability in &mut abilities.0 {
+            ability.ready_in = ability.ready_in.saturating_sub(1);
+        }
+    }
+    for (_, mana) in gs.world.query_mut::<&mut Mana>() {
+        if mana.current >= mana.max {
+            mana.counter = 0;
+            continue;
+        }
+        mana.counter += 1;
+        if mana.counter >= MANA_REGEN_INTERVAL {
+            mana.counter = 0;
+            mana.current += 1;
+        }
+    }
+}
+
+impl State {
+    /// Use one of the player's abilities, asking for a target first when it needs one
+    pub fn select_ability(&mut self, index: usize) -> bool {
+        type Q<'w> = (&'w Position, &'w Abilities, Option<&'w Mana>);
+        let Ok((&pos, abilities, mana)) = self.world.query_one_mut::<Q>(self.player) else {
+            return false;
+        };
+        let Some(ability) = abilities.0.get(index) else {
+            return false;
+        };
+        if ability.ready_in > 0 {
+            let msg = format!(
+                "{} is not ready for {} turns.",
+                ability.name, ability.ready_in
+            );
+            self.msg_log.push(msg);
+            return false;
+        }
+        if !ability.ready(mana) {
+            self.msg_log
+                .push(format!("Not enough mana to cast {}.", ability.name));
+            return false;
+        }
+        if ability.targeting == Targeting::Caster {
+            let cast = WantsToCast {
+                ability: index,
+                target: pos,
+            };
+            return self.world.insert_one(self.player, cast).is_ok();
+        }
+        self.phase = Phase::Targeting { ability: index };
+        false
+    }
+
+    /// Pick a target with the mouse, or the nearest monster in view with Enter.
+    /// Returns `None` while still choosing and whether the ability was cast once done.
+    pub fn target_ability(&mut self, ctx: &BTerm, index: usize) -> Option<bool> {
+        let target = match ctx.key {
+            Some(VirtualKeyCode::Escape) => return Some(false),
+            Some(VirtualKeyCode::Return) => self.nearest_monster()?.1,
+            _ if ctx.left_click => {
+                Position::try_from(ctx.mouse_point() - self.camera_offset()).ok()?
+            }
+            _ => return None,
+        };
+        let origin = *self.world.get::<&Position>(self.player).ok()?;
+        let targeting = self.world.get::<&Abilities>(self.player).ok()?.0[index].targeting;
+        if !self.map.in_bounds(target.into())
+            || !self.map.visible[target.into()]
+            || affected_tiles(&self.map, targeting, origin, target).is_empty()
+        {
+            self.msg_log.push("That is out of reach.".into());
+            return None;
+        }
+        let cast = WantsToCast {
+            ability: index,
+            target,
+        };
+        Some(self.world.insert_one(self.player, cast).is_ok())
+    }
+
+    pub fn nearest_monster(&self) -> Option<(Entity, Position)> {
+        let player_pos = *self.world.get::<&Position>(self.player).ok()?;
+        self.world
+            .query::<&Position>()
+            .with::<&Monster>()
+            .iter()
+            .map(|(e, pos)| (e, *pos))
+            .filter(|&(_, pos)| self.map.visible[pos.into()])
+            .min_by(|&(_, a), &(_, b)| {
+                let distance =
+                    |p: Position| DistanceAlg::Pythagoras.distance2d(player_pos.into(), p.into());
+                distance(a).total_cmp(&distance(b))
+            })
+    }
+
+    /// Highlight what the ability would reach if aimed at the mouse cursor
+    pub fn draw_targeting(&mut self, ctx: &mut BTerm, index: usize) {
+        let Ok(origin) = self.world.get::<&Position>(self.player).map(|pos| *pos) else {
+            return;
+        };
+        let Some(ability) = self
+            .world
+            .get::<&Abilities>(self.player)
+            .ok()
+            .and_then(|abilities| abilities.0.get(index).cloned())
+        else {
+            return;
+        };
+
+        // redraw the map to clear the highlight of the previous cursor position
+        self.render(ctx);
+        self.draw_ui(ctx);
+        let offset = self.camera_offset();
+        if let Ok(target) = Position::try_from(ctx.mouse_point() - offset) {
+            if self.map.in_bounds(target.into()) && self.map.visible[target.into()] {
+                let color = ability.color * 0.5;
+                ctx.set_active_console(0);
+                for pos in affected_tiles(&self.map, ability.targeting, origin, target) {
+                    let p = Point::from(pos) + offset;
+                    ctx.set_bg(p.x, p.y, color);
+                }
+            }
+        }
+
+        ctx.set_active_console(1);
+        ctx.cls();
+        self.draw_awareness(ctx);
+        let help = format!("{}: click or Enter to aim, Escape to cancel", ability.name);
+        ctx.print_color(1, 0, WHITE, BLACK, help);
+    }
+}
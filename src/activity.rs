@@ -0,0 +1,235 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use bracket_lib::prelude::*;
+use hecs::Entity;
+
+use crate::{
+    combat::CombatStats, corpse::Remains, monster::Monster, noise::RUN_LOUDNESS,
+    position::Position, trap::Hidden, Name, Renderable, State,
+};
+
+const EXPLORE_DEPTH: f32 = 1000.0;
+
+/// Something the player keeps doing over several turns without further input
+#[derive(Debug, Clone, Copy)]
+pub enum ActivityKind {
+    Explore,
+    /// Keep moving in a direction, following corridors, until something changes
+    Run {
+        dir: Point,
+        /// Number of exits around the previous tile
+        exits: Option<usize>,
+    },
+    Travel {
+        target: Position,
+    },
+    /// Pass turns until fully healed
+    Rest,
+}
+
+impl ActivityKind {
+    pub fn run(dx: i32, dy: i32) -> Self {
+        ActivityKind::Run {
+            dir: Point::new(dx, dy),
+            exits: None,
+        }
+    }
+}
+
+impl fmt::Display for ActivityKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ActivityKind::Explore => "exploring".fmt(f),
+            ActivityKind::Run { .. } => "running".fmt(f),
+            ActivityKind::Travel { .. } => "travelling".fmt(f),
+            ActivityKind::Rest => "resting".fmt(f),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Activity {
+    kind: ActivityKind,
+    /// Player HP after the last step, any drop interrupts the activity
+    hp: i32,
+    /// Things already noticed, so only new ones interrupt the activity
+    seen: HashSet<Entity>,
+}
+
+impl State {
+    /// Begin a new activity and take its first step
+    pub fn start_activity(&mut self, kind: ActivityKind) -> bool {
+        let mut activity = Activity {
+            kind,
+            hp: self.player_hp(),
+            seen: Default::default(),
+        };
+        if let Some(name) = self.visible_monster() {
+            self.msg_log
+                .push(format!("You can't start {kind} with {name} in view."));
+            return false;
+        }
+        // whatever is in view right now should not stop us on the first step
+        while self.interruption(&mut activity).is_some() {}
+        self.activity = Some(activity);
+        self.continue_activity()
+    }
+
+    /// Take the next step of the current activity, returns `true` if a turn was used
+    pub fn continue_activity(&mut self) -> bool {
+        let Some(mut activity) = self.activity.take() else {
+            return false;
+        };
+        if let Some(reason) = self.interruption(&mut activity) {
+            self.msg_log
+                .push(format!("You stop {}: {reason}.", activity.kind));
+            return false;
+        }
+        let took_turn = match &mut activity.kind {
+            ActivityKind::Explore => self.explore_step(),
+            ActivityKind::Run { dir, exits } => self.run_step(dir, exits),
+            &mut ActivityKind::Travel { target } => self.travel_step(target),
+            ActivityKind::Rest => self.rest_step(),
+        };
+        if took_turn {
+            self.activity = Some(activity);
+        }
+        took_turn
+    }
+
+    pub fn stop_activity(&mut self) {
+        if let Some(activity) = self.activity.take() {
+            self.msg_log.push(format!("You stop {}.", activity.kind));
+        }
+    }
+
+    fn interruption(&self, activity: &mut Activity) -> Option<String> {
+        if let Some(name) = self.visible_monster() {
+            return Some(format!("{name} comes into view"));
+        }
+
+        let hp = self.player_hp();
+        if hp < activity.hp {
+            return Some("you are hurt".into());
+        }
+        activity.hp = hp;
+
+        type Q<'w> = (&'w Position, &'w Name);
+        for (e, (pos, name)) in self
+            .world
+            .query::<Q>()
+            .with::<&Renderable>()
+            .without::<&CombatStats>()
+            .without::<&Hidden>()
+            .without::<&Remains>()
+            .iter()
+        {
+            if self.map.visible[pos.into()] && activity.seen.insert(e) {
+                return Some(format!("you see {name}"));
+            }
+        }
+        None
+    }
+
+    fn visible_monster(&self) -> Option<String> {
+        self.world
+            .query::<(&Position, &Name)>()
+            .with::<&Monster>()
+            .iter()
+            .find(|(_, (pos, _))| self.map.visible[(*pos).into()])
+            .map(|(_, (_, name))| name.to_string())
+    }
+
+    fn player_hp(&self) -> i32 {
+        self.world
+            .get::<&CombatStats>(self.player)
+            .map(|stats| stats.hp)
+            .unwrap_or_default()
+    }
+
+    fn rest_step(&mut self) -> bool {
+        let Ok(stats) = self.world.query_one_mut::<&CombatStats>(self.player) else {
+            return false;
+        };
+        if stats.hp >= stats.max_hp {
+            self.msg_log.push("You feel rested.".into());
+            return false;
+        }
+        true
+    }
+
+    /// Walk one tile towards the closest revealed tile bordering unknown territory
+    fn explore_step(&mut self) -> bool {
+        let player_pos = *self.world.query_one_mut::<&Position>(self.player).unwrap();
+        let player_idx = self.map.to_idx(player_pos);
+
+        let frontier = self.map.frontier();
+        let dm = DijkstraMap::new(
+            self.map.width,
+            self.map.height,
+            &frontier,
+            &self.map,
+            EXPLORE_DEPTH,
+        );
+        if frontier.is_empty() || dm.map[player_idx] >= EXPLORE_DEPTH {
+            self.msg_log.push("Nothing left to explore.".into());
+            return false;
+        }
+
+        let Some(exit) = DijkstraMap::find_lowest_exit(&dm, player_idx, &self.map) else {
+            return false;
+        };
+        let step = Point::from(self.map.to_pos(exit)) - Point::from(player_pos);
+        self.try_move_player(step.x as i8, step.y as i8)
+    }
+
+    fn run_step(&mut self, dir: &mut Point, prev_exits: &mut Option<usize>) -> bool {
+        let player_pos = *self.world.query_one_mut::<&Position>(self.player).unwrap();
+        let here = Point::from(player_pos);
+        let back = here - *dir;
+        let exits: Vec<Point> = [
+            Point::new(here.x - 1, here.y),
+            Point::new(here.x + 1, here.y),
+            Point::new(here.x, here.y + 1),
+            Point::new(here.x, here.y - 1),
+        ]
+        .into_iter()
+        .filter(|&p| self.map.is_passable(p))
+        .collect();
+
+        if let Some(prev) = prev_exits.replace(exits.len()) {
+            let ahead: Vec<Point> = exits.iter().copied().filter(|&p| p != back).collect();
+            if prev == 2 && exits.len() == 2 && ahead.len() == 1 {
+                // follow the bend of a corridor
+                *dir = ahead[0] - here;
+            } else if prev != exits.len() {
+                return false;
+            }
+        }
+
+        if !self.map.is_passable(here + *dir) {
+            return false;
+        }
+        self.make_noise(player_pos, RUN_LOUDNESS);
+        self.try_move_player(dir.x as i8, dir.y as i8)
+    }
+
+    fn travel_step(&mut self, target: Position) -> bool {
+        let player_pos = *self.world.query_one_mut::<&Position>(self.player).unwrap();
+        if player_pos == target {
+            return false;
+        }
+        let path = a_star_search(
+            self.map.to_idx(player_pos),
+            self.map.to_idx(target),
+            &self.map,
+        );
+        if !path.success || path.steps.len() < 2 {
+            self.msg_log.push("You can't find a way there.".into());
+            return false;
+        }
+        let step = Point::from(self.map.to_pos(path.steps[1])) - Point::from(player_pos);
+        self.try_move_player(step.x as i8, step.y as i8)
+    }
+}
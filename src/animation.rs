@@ -0,0 +1,235 @@
+use std::collections::VecDeque;
+
+use bracket_lib::prelude::*;
+
+use crate::position::Position;
+
+/// Draws a custom effect, given the screen offset of the map and the eased progress from 0 to 1
+pub type FrameCallback = Box<dyn FnMut(&mut BTerm, Point, f32) + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Easing {
+    #[default]
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    pub fn apply(self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut if t < 0.5 => 2.0 * t * t,
+            Easing::EaseInOut => -1.0 + (4.0 - 2.0 * t) * t,
+        }
+    }
+}
+
+pub enum AnimationKind {
+    /// Hide whatever is on a tile, like an attacker lunging out of it
+    Blank {
+        pos: Position,
+    },
+    Glyph {
+        pos: Position,
+        glyph: FontCharType,
+        color: RGB,
+    },
+    /// Text that rises up from a tile as it plays
+    FloatingText {
+        pos: Position,
+        text: String,
+        color: RGB,
+    },
+    /// A glyph travelling along a path
+    Projectile {
+        path: Vec<Position>,
+        glyph: FontCharType,
+        color: RGB,
+    },
+    /// A burst growing out of `center` and fading away
+    Explosion {
+        center: Position,
+        radius: f32,
+        color: RGB,
+    },
+    Custom(FrameCallback),
+}
+
+pub struct Animation {
+    kind: AnimationKind,
+    duration: f32,
+    /// Frames to wait before starting, to stagger animations played together
+    delay: f32,
+    frame: f32,
+    easing: Easing,
+}
+
+impl Animation {
+    pub fn new(kind: AnimationKind, duration: u32) -> Self {
+        Self {
+            kind,
+            duration: duration as f32,
+            delay: 0.0,
+            frame: 0.0,
+            easing: Easing::default(),
+        }
+    }
+
+    pub fn with_delay(mut self, delay: u32) -> Self {
+        self.delay = delay as f32;
+        self
+    }
+
+    pub fn with_easing(mut self, easing: Easing) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    pub fn floating_text(pos: Position, text: impl ToString, color: RGB) -> Self {
+        let text = text.to_string();
+        Self::new(AnimationKind::FloatingText { pos, text, color }, 12).with_easing(Easing::EaseOut)
+    }
+
+    pub fn projectile(path: Vec<Position>, glyph: char, color: RGB) -> Self {
+        let duration = path.len() as u32 * 2;
+        let glyph = to_cp437(glyph);
+        Self::new(AnimationKind::Projectile { path, glyph, color }, duration)
+            .with_easing(Easing::EaseIn)
+    }
+
+    pub fn explosion(center: Position, radius: f32, color: RGB) -> Self {
+        Self::new(
+            AnimationKind::Explosion {
+                center,
+                radius,
+                color,
+            },
+            10,
+        )
+        .with_easing(Easing::EaseOut)
+    }
+
+    pub fn custom(duration: u32, on_frame: FrameCallback) -> Self {
+        Self::new(AnimationKind::Custom(on_frame), duration)
+    }
+
+    /// Draw the next frame and indicate if the animation is finished
+    fn advance(&mut self, ctx: &mut BTerm, offset: Point, speed: f32) -> bool {
+        if self.delay > 0.0 {
+            self.delay -= speed;
+            return false;
+        }
+        let t = self.easing.apply((self.frame / self.duration).min(1.0));
+        let screen = |pos: Position| Point::from(pos) + offset;
+
+        match &mut self.kind {
+            AnimationKind::Blank { pos } => {
+                let p = screen(*pos);
+                ctx.print_color(p.x, p.y, WHITE, BLACK, ' ');
+            }
+            AnimationKind::Glyph { pos, glyph, color } => {
+                let p = screen(*pos);
+                ctx.set(p.x, p.y, *color, BLACK, *glyph);
+            }
+            AnimationKind::FloatingText { pos, text, color } => {
+                let p = screen(*pos);
+                // rising off the top of the screen would wrap around to the bottom
+                let rise = (t * 2.0).round() as i32;
+                ctx.print_color(p.x, (p.y - rise).max(0), *color, BLACK, &*text);
+            }
+            AnimationKind::Projectile { path, glyph, color } => {
+                if let Some(&pos) =
+                    path.get((t * path.len().saturating_sub(1) as f32).round() as usize)
+                {
+                    let p = screen(pos);
+                    ctx.set(p.x, p.y, *color, BLACK, *glyph);
+                }
+            }
+            AnimationKind::Explosion {
+                center,
+                radius,
+                color,
+            } => {
+                let c = screen(*center);
+                let current = *radius * t;
+                let r = radius.ceil() as i32;
+                let fade = *color * (1.0 - t);
+                for y in c.y - r..=c.y + r {
+                    for x in c.x - r..=c.x + r {
+                        let d = DistanceAlg::Pythagoras.distance2d(c, Point::new(x, y));
+                        if d <= current && x >= 0 && y >= 0 {
+                            ctx.set(x, y, fade, BLACK, to_cp437('*'));
+                        }
+                    }
+                }
+            }
+            AnimationKind::Custom(on_frame) => on_frame(ctx, offset, t),
+        }
+
+        self.frame += speed;
+        self.frame > self.duration
+    }
+}
+
+/// Animations waiting to be played, in groups that play at the same time
+#[derive(Default)]
+pub struct Animations {
+    queue: VecDeque<Vec<Animation>>,
+    /// Added since the last group was closed
+    pending: Vec<Animation>,
+    playing: Vec<Animation>,
+}
+
+impl Animations {
+    pub fn add(&mut self, animation: Animation) {
+        self.pending.push(animation);
+    }
+
+    /// Whatever was added so far plays together, before anything added later
+    pub fn end_group(&mut self) {
+        if !self.pending.is_empty() {
+            self.queue.push_back(std::mem::take(&mut self.pending));
+        }
+    }
+
+    pub fn skip(&mut self) {
+        self.queue.clear();
+        self.pending.clear();
+        self.playing.clear();
+    }
+
+    pub fn miss(&mut self, src: Position, dst: Position) {
+        self.add(Animation::new(AnimationKind::Blank { pos: src }, 2));
+        self.add(Animation::new(
+            AnimationKind::Glyph {
+                pos: dst,
+                glyph: to_cp437('/'),
+                color: RGB::named(WHITE),
+            },
+            5,
+        ));
+    }
+
+    pub fn melee(&mut self, src: Position, dst: Position, dmg: i32) {
+        self.add(Animation::new(AnimationKind::Blank { pos: src }, 2));
+        self.add(Animation::floating_text(dst, dmg, RGB::named(WHITE)).with_delay(1));
+    }
+
+    /// Play the next frame, returns `true` once there is nothing left to play
+    pub fn advance(&mut self, ctx: &mut BTerm, offset: Point, speed: f32) -> bool {
+        self.end_group();
+        if self.playing.is_empty() {
+            match self.queue.pop_front() {
+                Some(group) => self.playing = group,
+                None => return true,
+            }
+        }
+        self.playing
+            .retain_mut(|animation| !animation.advance(ctx, offset, speed));
+        false
+    }
+}
@@ -0,0 +1,72 @@
+use bracket_lib::prelude::BaseMap;
+use hecs::Entity;
+
+use crate::{
+    ability::{Abilities, Ability},
+    combat::{CombatStats, Percentage},
+    formula::Dice,
+    monster::Asleep,
+    position::Position,
+    spawn,
+    stealth::Awareness,
+    Name, State,
+};
+
+/// Fraction of its HP a boss drops to before each new phase of the fight
+const PHASES: [f32; 2] = [0.66, 0.33];
+
+/// A unique monster changing its tactics as it gets hurt
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Boss {
+    /// Number of phases the boss went through, 0 while the fight has not turned yet
+    pub phase: usize,
+}
+
+/// Move bosses on to their next phase once they are hurt enough
+pub fn run(gs: &mut State) {
+    let mut turning = Vec::new();
+    for (e, (boss, stats)) in gs.world.query_mut::<(&mut Boss, &CombatStats)>() {
+        let health = stats.hp as f32 / stats.max_hp as f32;
+        while boss.phase < PHASES.len() && health <= PHASES[boss.phase] {
+            boss.phase += 1;
+            turning.push((e, boss.phase));
+        }
+    }
+    for (e, phase) in turning {
+        match phase {
+            1 => enrage(gs, e),
+            _ => call_for_help(gs, e),
+        }
+    }
+}
+
+/// Hit harder and more often
+fn enrage(gs: &mut State, boss: Entity) {
+    let Ok((stats, name)) = gs.world.query_one_mut::<(&mut CombatStats, &Name)>(boss) else {
+        return;
+    };
+    stats.damage = Dice::new(2, 8, stats.damage.bonus);
+    stats.accuracy = Percentage::new(stats.accuracy.value() + 0.1);
+    gs.msg_log.push(format!("The {name} flies into a rage!"));
+}
+
+/// Bring in two minions and start throwing fireballs
+fn call_for_help(gs: &mut State, boss: Entity) {
+    let Ok((pos, name)) = gs.world.query_one_mut::<(&Position, &Name)>(boss) else {
+        return;
+    };
+    let pos = *pos;
+    gs.msg_log.push(format!("The {name} roars for help!"));
+
+    let exits = gs.map.get_available_exits(gs.map.to_idx(pos));
+    for &(idx, _) in exits.iter().take(2) {
+        let minion_pos = gs.map.to_pos(idx);
+        let minion = spawn::monster(&mut gs.world, &mut gs.rng, minion_pos);
+        _ = gs.world.remove_one::<Asleep>(minion);
+        _ = gs.world.insert_one(minion, Awareness::alert());
+        gs.place(minion);
+    }
+    if let Ok(abilities) = gs.world.query_one_mut::<&mut Abilities>(boss) {
+        abilities.0.push(Ability::fireball());
+    }
+}
@@ -1,7 +1,17 @@
 use bracket_lib::terminal::console;
 use hecs::{Entity, World};
 
-use crate::{position::Position, Animation, Name, State};
+use crate::{
+    corpse,
+    damage::{Damage, DamageType, Resistances},
+    event::{Actor, Event},
+    formula::{Dice, Outcome},
+    monster::{Asleep, Monster},
+    noise::{Noise, COMBAT_LOUDNESS},
+    position::Position,
+    stealth::{Awareness, AwarenessLevel},
+    Name, State,
+};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Percentage(f32);
@@ -9,6 +19,9 @@ impl Percentage {
     pub fn new(value: f32) -> Self {
         Self(value.clamp(0.0, 1.0))
     }
+    pub fn value(self) -> f32 {
+        self.0
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -16,8 +29,30 @@ pub struct CombatStats {
     pub max_hp: i32,
     pub hp: i32,
     pub accuracy: Percentage,
-    pub defense: Percentage,
-    pub power: i32,
+    /// Chance of dodging an attack, taken off the attacker's accuracy
+    pub evasion: Percentage,
+    /// Flat reduction of every hit's damage
+    pub armor: i32,
+    pub damage: Dice,
+    pub damage_type: DamageType,
+}
+
+/// Natural healing of `amount` HP every `interval` turns
+#[derive(Debug, Clone, Copy)]
+pub struct Regeneration {
+    pub interval: u32,
+    pub amount: i32,
+    counter: u32,
+}
+
+impl Regeneration {
+    pub fn new(interval: u32, amount: i32) -> Self {
+        Self {
+            interval,
+            amount,
+            counter: 0,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -27,19 +62,19 @@ pub struct WantsToMelee {
 
 #[derive(Debug, Clone)]
 pub struct SufferDamage {
-    pub queue: Vec<i32>,
+    pub queue: Vec<Damage>,
 }
 
 impl SufferDamage {
-    pub fn new(amount: i32) -> Self {
+    pub fn new(damage: Damage) -> Self {
         Self {
-            queue: vec![amount],
+            queue: vec![damage],
         }
     }
-    pub fn add_damage(world: &mut World, victim: Entity, amount: i32) {
+    pub fn add_damage(world: &mut World, victim: Entity, damage: Damage) {
         if let Ok(suffering) = world.query_one_mut::<&mut SufferDamage>(victim) {
-            suffering.queue.push(amount);
-        } else if let Err(err) = world.insert_one(victim, SufferDamage::new(amount)) {
+            suffering.queue.push(damage);
+        } else if let Err(err) = world.insert_one(victim, SufferDamage::new(damage)) {
             console::log(format!("Error inserting Damage component: {err}"));
         }
     }
@@ -57,35 +92,69 @@ pub fn melee_combat(gs: &mut State) {
         if stats.hp <= 0 {
             continue;
         }
-        let Ok(mut target) = gs
-            .world
-            .query_one::<(&Position, &CombatStats, &Name)>(wants_melee.target)
-        else {
+        type T<'w> = (
+            &'w Position,
+            &'w CombatStats,
+            &'w Name,
+            Option<&'w Awareness>,
+            Option<&'w Asleep>,
+            Option<&'w Resistances>,
+        );
+        let Ok(mut target) = gs.world.query_one::<T>(wants_melee.target) else {
             continue;
         };
-        if let Some((target_pos, target_stats, target_name)) = target.get() {
+        if let Some((target_pos, target_stats, target_name, awareness, asleep, resistances)) =
+            target.get()
+        {
             if target_stats.hp <= 0 {
                 continue;
             }
-            let raw_damage = stats.power as f32 * (1.0 - target_stats.defense.0);
-            let (mut damage, fractional) = (raw_damage as i32, raw_damage.fract());
-            if gs.rng.range(0.0, 1.0) > fractional {
-                damage += 1;
+            gs.noises.push(Noise::new(*attacker_pos, COMBAT_LOUDNESS));
+            let surprise =
+                asleep.is_some() || awareness.is_some_and(|a| a.level() == AwarenessLevel::Unaware);
+            let attack = gs
+                .formula
+                .attack(&mut gs.rng, stats, target_stats, surprise);
+            if gs.options.log_rolls {
+                for roll in &attack.rolls {
+                    console::log(format!("[combat] {name} -> {target_name}: {roll}"));
+                }
             }
-            if stats.accuracy.0 < gs.rng.range(0.0, 1.0) {
-                gs.msg_log.push(format!("{name} is misses {target_name}"));
-                gs.animation_queue
-                    .push_back(Animation::miss(*attacker_pos, *target_pos));
-            } else {
-                gs.msg_log.push(format!(
-                    "{name} hits {target_name} for {} [blocked {}]",
-                    damage,
-                    damage - raw_damage as i32
-                ));
-                gs.animation_queue
-                    .push_back(Animation::melee(*attacker_pos, *target_pos, damage));
-                to_damage.push((wants_melee.target, damage));
+
+            let attacker = Actor {
+                entity: e,
+                name: name.to_string(),
+                pos: *attacker_pos,
+            };
+            let target = Actor {
+                entity: wants_melee.target,
+                name: target_name.to_string(),
+                pos: *target_pos,
+            };
+            if matches!(attack.outcome, Outcome::Fumble | Outcome::Miss) {
+                gs.events.publish(Event::Missed {
+                    attacker,
+                    target,
+                    fumble: attack.outcome == Outcome::Fumble,
+                });
+                continue;
             }
+            // tell of the damage the target will actually take
+            let damage = resistances
+                .and_then(|resistances| resistances.get(stats.damage_type))
+                .map_or(attack.damage, |r| r.apply(attack.damage));
+            gs.events.publish(Event::Attacked {
+                attacker,
+                target,
+                outcome: attack.outcome,
+                damage,
+                kind: stats.damage_type,
+                blocked: attack.blocked,
+            });
+            to_damage.push((
+                wants_melee.target,
+                Damage::new(attack.damage, stats.damage_type).by(e),
+            ));
         }
     }
     for (target, dmg) in to_damage {
@@ -100,10 +169,33 @@ pub fn melee_combat(gs: &mut State) {
 
 pub fn apply_damage(gs: &mut State) {
     let mut victims = Vec::new();
-    for (e, (combat_stats, suffer_damage)) in
-        gs.world.query_mut::<(&mut CombatStats, &SufferDamage)>()
-    {
-        combat_stats.hp -= suffer_damage.queue.iter().sum::<i32>();
+    let god_mode = gs.god_mode();
+    type Q<'w> = (
+        &'w mut CombatStats,
+        &'w SufferDamage,
+        Option<&'w Resistances>,
+        Option<&'w Name>,
+    );
+    for (e, (combat_stats, suffer_damage, resistances, name)) in gs.world.query_mut::<Q>() {
+        for damage in &suffer_damage.queue {
+            if god_mode && e == gs.player {
+                continue;
+            }
+            let resistance = damage
+                .kind
+                .zip(resistances)
+                .and_then(|(kind, resistances)| resistances.get(kind));
+            let amount = resistance.map_or(damage.amount, |r| r.apply(damage.amount));
+            combat_stats.hp -= amount;
+            gs.events.publish(Event::Damaged {
+                victim: e,
+                name: name.map(|name| name.to_string()),
+                source: damage.source,
+                amount,
+                kind: damage.kind,
+                resistance,
+            });
+        }
         victims.push(e);
     }
     for e in victims {
@@ -115,18 +207,39 @@ pub fn apply_damage(gs: &mut State) {
 
 pub fn delete_the_dead(gs: &mut State) {
     let mut dead = Vec::new();
-    for (e, (stats, name)) in gs.world.query_mut::<(&CombatStats, &Name)>() {
+    type Q<'w> = (&'w CombatStats, &'w Name, Option<&'w Monster>);
+    for (e, (stats, name, monster)) in gs.world.query_mut::<Q>() {
         if stats.hp <= 0 {
-            if e == gs.player {
-                gs.msg_log.push(format!("{name} died. Game over"));
-            } else {
-                gs.msg_log.push(format!("{name} is dead."));
-            }
+            gs.events.publish(Event::Died {
+                entity: e,
+                name: name.to_string(),
+                monster: monster.is_some(),
+            });
             dead.push(e);
         }
     }
     for e in dead {
-        _ = gs.world.despawn(e);
+        if e != gs.player {
+            corpse::leave_remains(gs, e);
+        }
+        gs.despawn(e);
+    }
+}
+
+pub fn regenerate(gs: &mut State) {
+    for (_, (stats, regen)) in gs
+        .world
+        .query_mut::<(&mut CombatStats, &mut Regeneration)>()
+    {
+        if stats.hp <= 0 || stats.hp >= stats.max_hp {
+            regen.counter = 0;
+            continue;
+        }
+        regen.counter += 1;
+        if regen.counter >= regen.interval {
+            regen.counter = 0;
+            stats.hp = (stats.hp + regen.amount).min(stats.max_hp);
+        }
     }
 }
 
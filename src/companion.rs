@@ -0,0 +1,284 @@
+use std::fmt;
+
+use bracket_lib::prelude::*;
+use hecs::Entity;
+
+use crate::{
+    combat::WantsToMelee,
+    event::Event,
+    monster::{self, Monster},
+    movement::WantsToMove,
+    position::Position,
+    status::Confused,
+    stealth::{Awareness, AwarenessLevel},
+    Name, State, ViewShed,
+};
+
+/// Companions following the player try to stay this close
+const FOLLOW_DISTANCE: f32 = 2.5;
+/// Alert monsters this close to the player are a threat companions go after
+const GUARD_DISTANCE: f32 = 6.0;
+/// How far companions look for a way to reach their target
+const CHASE_DEPTH: f32 = 30.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Command {
+    /// Keep close to the player and fight off threats
+    #[default]
+    Follow,
+    /// Hold position, only fighting what comes next to them
+    Stay,
+    /// Go after this entity until it is dead
+    Attack(Entity),
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::Follow => "following".fmt(f),
+            Command::Stay => "staying".fmt(f),
+            Command::Attack(_) => "attacking".fmt(f),
+        }
+    }
+}
+
+/// Fights on the player's side
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Companion {
+    pub command: Command,
+}
+
+/// Joins the player when asked to
+#[derive(Debug)]
+pub struct Recruitable;
+
+/// Vanishes once its time is up
+#[derive(Debug, Clone, Copy)]
+pub struct Summoned {
+    pub turns: i32,
+}
+
+/// The last creature the entity attacked
+#[derive(Debug, Clone, Copy)]
+pub struct Engaged(pub Entity);
+
+pub fn run(gs: &mut State) {
+    dismiss_summons(gs);
+
+    let player_pos = *gs.world.query_one_mut::<&Position>(gs.player).unwrap();
+    let engaged = gs
+        .world
+        .get::<&Engaged>(gs.player)
+        .ok()
+        .map(|engaged| engaged.0)
+        .filter(|&target| gs.world.contains(target));
+    let threats: Vec<(Entity, Position)> = gs
+        .world
+        .query::<(&Position, &Awareness)>()
+        .with::<&Monster>()
+        .iter()
+        .filter(|(_, (pos, awareness))| {
+            awareness.level() == AwarenessLevel::Alert
+                && distance(**pos, player_pos) <= GUARD_DISTANCE
+        })
+        .map(|(e, (pos, _))| (e, *pos))
+        .collect();
+
+    let companions: Vec<(Entity, Command)> = gs
+        .world
+        .query_mut::<&Companion>()
+        .into_iter()
+        .map(|(e, companion)| (e, companion.command))
+        .collect();
+    for (e, command) in companions {
+        act(gs, e, command, player_pos, engaged, &threats);
+    }
+}
+
+/// Remember who everybody last fought, so companions can join in
+pub fn engage(gs: &mut State, event: &Event) {
+    if let Event::Attacked {
+        attacker, target, ..
+    }
+    | Event::Missed {
+        attacker, target, ..
+    } = event
+    {
+        _ = gs.world.insert_one(attacker.entity, Engaged(target.entity));
+    }
+}
+
+fn distance(a: Position, b: Position) -> f32 {
+    DistanceAlg::Pythagoras.distance2d(a.into(), b.into())
+}
+
+fn dismiss_summons(gs: &mut State) {
+    let mut expired = Vec::new();
+    for (e, summoned) in gs.world.query_mut::<&mut Summoned>() {
+        summoned.turns -= 1;
+        if summoned.turns <= 0 {
+            expired.push(e);
+        }
+    }
+    for e in expired {
+        if let Ok(name) = gs.world.get::<&Name>(e) {
+            gs.msg_log.push(format!("The {name} fades away."));
+        }
+        gs.despawn(e);
+    }
+}
+
+fn act(
+    gs: &mut State,
+    e: Entity,
+    command: Command,
+    player_pos: Position,
+    engaged: Option<Entity>,
+    threats: &[(Entity, Position)],
+) {
+    let Ok(pos) = gs.world.get::<&Position>(e).map(|pos| *pos) else {
+        return;
+    };
+    if gs.world.satisfies::<&Confused>(e).unwrap_or(false) {
+        let dst = pos + monster::random_direction(&mut gs.rng);
+        step(gs, e, dst, player_pos);
+        return;
+    }
+
+    let target = {
+        let Ok(fov) = gs.world.get::<&ViewShed>(e) else {
+            return;
+        };
+        let position_of = |target: Entity| {
+            let pos = *gs.world.get::<&Position>(target).ok()?;
+            Some((target, pos))
+        };
+        let in_view = |&(_, p): &(Entity, Position)| fov.visible_tiles.contains(&p);
+        match command {
+            Command::Attack(target) => position_of(target),
+            Command::Follow | Command::Stay => {
+                engaged.and_then(position_of).filter(in_view).or_else(|| {
+                    threats
+                        .iter()
+                        .copied()
+                        .filter(in_view)
+                        .min_by(|a, b| distance(pos, a.1).total_cmp(&distance(pos, b.1)))
+                })
+            }
+        }
+    };
+
+    match target {
+        Some((target, target_pos))
+            if DistanceAlg::Manhattan.distance2d(pos.into(), target_pos.into()) <= 1.0 =>
+        {
+            _ = gs.world.insert_one(e, WantsToMelee { target });
+        }
+        Some((_, target_pos)) if command != Command::Stay => {
+            let dm = DijkstraMap::new(
+                gs.map.width,
+                gs.map.height,
+                &[gs.map.to_idx(target_pos)],
+                &gs.map,
+                CHASE_DEPTH,
+            );
+            if let Some(dst) = downhill(gs, &dm, pos, player_pos) {
+                step(gs, e, dst, player_pos);
+            }
+        }
+        None if matches!(command, Command::Attack(_)) => {
+            // the target is gone
+            if let Ok(companion) = gs.world.query_one_mut::<&mut Companion>(e) {
+                companion.command = Command::Follow;
+            }
+        }
+        None if command == Command::Follow && distance(pos, player_pos) > FOLLOW_DISTANCE => {
+            if let Some(dst) = downhill(gs, &gs.dm, pos, player_pos) {
+                step(gs, e, dst, player_pos);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The neighbouring tile closest to the goal of `dm`, without walking into the player
+fn downhill(gs: &State, dm: &DijkstraMap, pos: Position, player_pos: Position) -> Option<Position> {
+    let here = gs.map.to_idx(pos);
+    let player = gs.map.to_idx(player_pos);
+    gs.map
+        .get_available_exits(here)
+        .into_iter()
+        .map(|(idx, _)| idx)
+        .filter(|&idx| idx != player && dm.map[idx] < dm.map[here])
+        .min_by(|&a, &b| dm.map[a].total_cmp(&dm.map[b]))
+        .map(|idx| gs.map.to_pos(idx))
+}
+
+fn step(gs: &mut State, e: Entity, dst: Position, player_pos: Position) {
+    if dst == player_pos || !gs.map.is_passable(dst) {
+        return;
+    }
+    _ = gs.world.insert_one(e, WantsToMove { destination: dst });
+}
+
+impl State {
+    /// Ask a creature next to the player to come along
+    pub fn recruit(&mut self) -> bool {
+        let player_pos = *self.world.query_one_mut::<&Position>(self.player).unwrap();
+        let found = self
+            .world
+            .query::<(&Position, &Name)>()
+            .with::<&Recruitable>()
+            .iter()
+            .find(|(_, (pos, _))| {
+                DistanceAlg::Manhattan.distance2d((*pos).into(), player_pos.into()) <= 1.0
+            })
+            .map(|(e, (_, name))| (e, name.to_string()));
+        let Some((e, name)) = found else {
+            self.msg_log.push("There is no one here to recruit.".into());
+            return false;
+        };
+        _ = self.world.remove_one::<Recruitable>(e);
+        _ = self.world.insert_one(e, Companion::default());
+        self.msg_log.push(format!("The {name} joins you."));
+        true
+    }
+
+    /// Give the same order to every companion, it takes no time
+    pub fn command_companions(&mut self, command: Command) -> bool {
+        let mut count = 0;
+        for (_, companion) in self.world.query_mut::<&mut Companion>() {
+            companion.command = command;
+            count += 1;
+        }
+        if count == 0 {
+            self.msg_log.push("You have no companions.".into());
+        } else {
+            self.msg_log.push(format!("Your companions are {command}."));
+        }
+        false
+    }
+
+    /// Send the companions after the monster under the mouse cursor, or the nearest one in view
+    pub fn order_attack(&mut self, ctx: &BTerm) -> bool {
+        let mouse = Position::try_from(ctx.mouse_point() - self.camera_offset()).ok();
+        let target = mouse
+            .filter(|&pos| self.map.in_bounds(pos.into()) && self.map.visible[pos.into()])
+            .and_then(|pos| {
+                self.map
+                    .index
+                    .at(pos)
+                    .iter()
+                    .copied()
+                    .find(|&e| self.world.satisfies::<&Monster>(e).unwrap_or(false))
+            })
+            .or_else(|| self.nearest_monster().map(|(e, _)| e));
+        match target {
+            Some(target) => self.command_companions(Command::Attack(target)),
+            None => {
+                self.msg_log.push("There is nothing to attack.".into());
+                false
+            }
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use bracket_lib::prelude::*;
+use grid::Grid;
+
+use crate::{
+    map::{Map, TileType},
+    position::Position,
+};
+
+/// Unreachable pockets of floor smaller than this are filled in rather than dug out
+const MIN_REGION: usize = 4;
+/// Levels with less floor than this are too cramped to play
+const MIN_FLOOR_RATIO: f32 = 0.15;
+const MIN_ROOMS: usize = 4;
+
+const NEIGHBOURS: [(i32, i32); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+
+/// Numbers describing a level, for tuning the generators
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MapStats {
+    pub width: usize,
+    pub height: usize,
+    pub floor: usize,
+    pub rooms: usize,
+    /// Floor tiles with a single way out
+    pub dead_ends: usize,
+    /// Separate areas of floor, 1 on a fully connected level
+    pub regions: usize,
+    /// Floor tiles that cannot be reached from the start
+    pub unreachable: usize,
+}
+
+impl MapStats {
+    pub fn floor_ratio(&self) -> f32 {
+        self.floor as f32 / (self.width * self.height) as f32
+    }
+}
+
+impl fmt::Display for MapStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "size:        {}x{}", self.width, self.height)?;
+        writeln!(
+            f,
+            "floor:       {} ({:.1}%)",
+            self.floor,
+            self.floor_ratio() * 100.0
+        )?;
+        writeln!(f, "rooms:       {}", self.rooms)?;
+        writeln!(f, "dead ends:   {}", self.dead_ends)?;
+        writeln!(f, "regions:     {}", self.regions)?;
+        write!(f, "unreachable: {}", self.unreachable)
+    }
+}
+
+impl Map {
+    fn is_floor(&self, p: Point) -> bool {
+        self.tiles.get(p.x, p.y) == Some(&TileType::Floor)
+    }
+
+    /// Flood fill from `start`, marking every tile that can be walked to.
+    /// Entities block tiles too, so this is meant for levels nothing was spawned on yet
+    pub fn reachable_from(&self, start: Position) -> Grid<bool> {
+        let mut reached: Grid<bool> = self.new_grid();
+        if !self.is_floor(start.into()) {
+            return reached;
+        }
+        let mut queue = VecDeque::from([self.to_idx(start)]);
+        reached[start.into()] = true;
+        while let Some(idx) = queue.pop_front() {
+            for (next, _) in self.get_available_exits(idx) {
+                let pos = self.to_pos(next);
+                if !reached[pos.into()] {
+                    reached[pos.into()] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+        reached
+    }
+
+    /// Every separate area of floor not marked in `reached`
+    fn unreached_regions(&self, reached: &Grid<bool>) -> Vec<Vec<Position>> {
+        let mut seen = reached.clone();
+        let mut regions = Vec::new();
+        for (idx, tile) in self.tiles.indexed_iter() {
+            if *tile != TileType::Floor || seen[idx] {
+                continue;
+            }
+            let start = Position::new(idx.0, idx.1);
+            let region: Vec<Position> = self
+                .reachable_from(start)
+                .indexed_iter()
+                .filter(|&(_, &r)| r)
+                .map(|((x, y), _)| Position::new(x, y))
+                .collect();
+            for &pos in &region {
+                seen[pos.into()] = true;
+            }
+            regions.push(region);
+        }
+        regions
+    }
+
+    /// Dig tunnels to unreachable areas, or fill them in when they are too small to matter
+    pub fn connect_regions(&mut self) {
+        loop {
+            let reached = self.reachable_from(self.start);
+            let Some(region) = self.unreached_regions(&reached).into_iter().next() else {
+                return;
+            };
+            if region.len() < MIN_REGION {
+                for pos in region {
+                    self.tiles[pos.into()] = TileType::Wall;
+                }
+            } else {
+                let from = Point::from(region[0]);
+                let to = reached
+                    .indexed_iter()
+                    .filter(|&(_, &r)| r)
+                    .map(|((x, y), _)| Point::new(x, y))
+                    .min_by(|&a, &b| {
+                        let distance = |p| DistanceAlg::Pythagoras.distance2d(from, p);
+                        distance(a).total_cmp(&distance(b))
+                    });
+                let Some(to) = to else {
+                    // the start itself is walled in, nothing to connect to
+                    return;
+                };
+                self.carve_horizontal_tunnel(from.x, to.x, from.y);
+                self.carve_vertical_tunnel(to.x, from.y, to.y);
+            }
+        }
+    }
+
+    /// Check that the level is playable, everything on it can be reached from the start
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.is_floor(self.start.into()) {
+            return Err("the start is not on the floor".into());
+        }
+        let stats = self.stats();
+        if stats.unreachable > 0 {
+            return Err(format!(
+                "{} floor tiles cannot be reached",
+                stats.unreachable
+            ));
+        }
+        if !self.rooms.is_empty() && stats.rooms < MIN_ROOMS {
+            return Err(format!("only {} rooms", stats.rooms));
+        }
+        if stats.floor_ratio() < MIN_FLOOR_RATIO {
+            return Err(format!("only {:.1}% floor", stats.floor_ratio() * 100.0));
+        }
+        let misplaced = self
+            .spawns
+            .iter()
+            .find(|(pos, _)| !self.is_floor((*pos).into()));
+        if let Some((pos, entity)) = misplaced {
+            return Err(format!("{entity} at {pos:?} is inside a wall"));
+        }
+        Ok(())
+    }
+
+    pub fn stats(&self) -> MapStats {
+        let reached = self.reachable_from(self.start);
+        let floor: Vec<Point> = self
+            .tiles
+            .indexed_iter()
+            .filter(|&(_, &tile)| tile == TileType::Floor)
+            .map(|((x, y), _)| Point::new(x, y))
+            .collect();
+        let dead_ends = floor
+            .iter()
+            .filter(|p| {
+                let exits = NEIGHBOURS
+                    .into_iter()
+                    .filter(|&(dx, dy)| self.is_floor(**p + Point::new(dx, dy)))
+                    .count();
+                exits == 1
+            })
+            .count();
+        let unreached = self.unreached_regions(&reached);
+
+        MapStats {
+            width: self.width,
+            height: self.height,
+            floor: floor.len(),
+            rooms: self.rooms.len(),
+            dead_ends,
+            regions: unreached.len() + usize::from(reached.iter().any(|&r| r)),
+            unreachable: unreached.iter().map(|region| region.len()).sum(),
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use bracket_lib::prelude::*;
+use hecs::Entity;
+
+use crate::{position::Position, Name, RenderOrder, Renderable, State};
+
+/// Turns before a corpse rots down to bones
+const CORPSE_DECAY: i32 = 200;
+
+/// What is left of a dead creature
+#[derive(Debug, Clone)]
+pub struct Remains {
+    /// Name of the creature these remains belonged to
+    pub of: String,
+    /// Turns until the corpse is reduced to bones, which last for good
+    pub decay: Option<i32>,
+}
+
+/// Things carried by a creature or left inside its remains
+#[derive(Debug, Clone, Default)]
+pub struct Contents(pub Vec<Entity>);
+
+/// Leave a corpse holding everything the dead creature carried
+pub fn leave_remains(gs: &mut State, dead: Entity) {
+    type Q<'w> = (
+        &'w Position,
+        &'w Name,
+        &'w Renderable,
+        Option<&'w mut Contents>,
+    );
+    let Ok((pos, name, render, contents)) = gs.world.query_one_mut::<Q>(dead) else {
+        return;
+    };
+    let position = *pos;
+    let of = name.to_string();
+    let fg = render.colors.fg;
+    let contents = contents
+        .map(|c| std::mem::take(&mut c.0))
+        .unwrap_or_default();
+
+    let corpse = gs.world.spawn((
+        position,
+        Name::new(format!("{of} corpse")),
+        Remains {
+            of,
+            decay: Some(CORPSE_DECAY),
+        },
+        Contents(contents),
+        Renderable {
+            glyph: to_cp437('%'),
+            colors: ColorPair {
+                fg: RGBA::from_f32(fg.r * 0.6, fg.g * 0.6, fg.b * 0.6, fg.a),
+                bg: RGBA::named(BLACK),
+            },
+            order: RenderOrder::Corpse,
+        },
+    ));
+    gs.place(corpse);
+}
+
+/// Let corpses rot, leaving bones behind
+pub fn decay(gs: &mut State) {
+    type Q<'w> = (&'w mut Remains, &'w mut Name, &'w mut Renderable);
+    for (_, (remains, name, render)) in gs.world.query_mut::<Q>() {
+        let Some(turns) = &mut remains.decay else {
+            continue;
+        };
+        *turns -= 1;
+        if *turns <= 0 {
+            remains.decay = None;
+            *name = Name::new(format!("{} bones", remains.of));
+            render.glyph = to_cp437('~');
+            render.colors.fg = RGBA::named(WHITE);
+        }
+    }
+}
+
+impl State {
+    /// Take everything out of the remains on the player's tile and drop it on the floor
+    pub fn loot(&mut self) -> bool {
+        let player_pos = *self.world.query_one_mut::<&Position>(self.player).unwrap();
+        let mut found = Vec::new();
+        for (_, (pos, name, contents)) in self
+            .world
+            .query_mut::<(&Position, &Name, &mut Contents)>()
+            .with::<&Remains>()
+        {
+            if *pos == player_pos && !contents.0.is_empty() {
+                found.push((name.to_string(), std::mem::take(&mut contents.0)));
+            }
+        }
+        let looted = !found.is_empty();
+
+        for (remains, items) in found {
+            for item in items {
+                _ = self.world.insert_one(item, player_pos);
+                self.place(item);
+                if let Ok(name) = self.world.get::<&Name>(item) {
+                    self.msg_log
+                        .push(format!("You find a {name} in the {remains}."));
+                }
+            }
+        }
+        let collected = self.collect_treasure(player_pos);
+        if !looted && !collected {
+            self.msg_log.push("There is nothing to loot here.".into());
+        }
+        looted || collected
+    }
+}
@@ -0,0 +1,93 @@
+use std::fmt;
+
+use hecs::Entity;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DamageType {
+    Slashing,
+    Piercing,
+    Blunt,
+    Fire,
+    Cold,
+    Poison,
+}
+
+impl fmt::Display for DamageType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DamageType::Slashing => "slashing".fmt(f),
+            DamageType::Piercing => "piercing".fmt(f),
+            DamageType::Blunt => "blunt".fmt(f),
+            DamageType::Fire => "fire".fmt(f),
+            DamageType::Cold => "cold".fmt(f),
+            DamageType::Poison => "poison".fmt(f),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resistance {
+    /// Takes half damage
+    Resistant,
+    /// Takes no damage
+    Immune,
+    /// Takes double damage
+    Vulnerable,
+}
+
+impl Resistance {
+    pub fn apply(self, amount: i32) -> i32 {
+        match self {
+            Resistance::Resistant => amount / 2,
+            Resistance::Immune => 0,
+            Resistance::Vulnerable => amount * 2,
+        }
+    }
+}
+
+/// How an entity reacts to each kind of damage, anything not listed is taken in full
+#[derive(Debug, Clone, Default)]
+pub struct Resistances(pub Vec<(DamageType, Resistance)>);
+
+impl Resistances {
+    pub fn get(&self, kind: DamageType) -> Option<Resistance> {
+        self.0
+            .iter()
+            .find(|&&(k, _)| k == kind)
+            .map(|&(_, resistance)| resistance)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Damage {
+    pub amount: i32,
+    /// Untyped damage, like starving, ignores resistances
+    pub kind: Option<DamageType>,
+    /// Whoever or whatever dealt the damage
+    pub source: Option<Entity>,
+}
+
+impl Damage {
+    pub fn new(amount: i32, kind: DamageType) -> Self {
+        Self {
+            amount,
+            kind: Some(kind),
+            source: None,
+        }
+    }
+
+    pub fn untyped(amount: i32) -> Self {
+        Self {
+            amount,
+            kind: None,
+            source: None,
+        }
+    }
+
+    pub fn by(self, source: Entity) -> Self {
+        Self {
+            source: Some(source),
+            ..self
+        }
+    }
+}
@@ -0,0 +1,306 @@
+use bracket_lib::terminal::console;
+use hecs::Entity;
+
+use crate::{
+    companion,
+    damage::{DamageType, Resistance},
+    formula::Outcome,
+    monster,
+    position::Position,
+    statistics,
+    trap::Hidden,
+    Item, Name, State,
+};
+
+/// Systems told about every event, in this order
+const SUBSCRIBERS: &[fn(&mut State, &Event)] = &[
+    log,
+    animate,
+    monster::wake,
+    companion::engage,
+    statistics::record,
+];
+
+/// Who took part in an event, captured when it happened since they may be gone by the time
+/// subscribers hear about it
+#[derive(Debug, Clone)]
+pub struct Actor {
+    pub entity: Entity,
+    pub name: String,
+    pub pos: Position,
+}
+
+/// Something that happened during play
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// A melee attack connected
+    Attacked {
+        attacker: Actor,
+        target: Actor,
+        outcome: Outcome,
+        /// Damage dealt, after the target's resistances
+        damage: i32,
+        kind: DamageType,
+        /// Damage taken off by the target's armor
+        blocked: i32,
+    },
+    /// A melee attack went wide
+    Missed {
+        attacker: Actor,
+        target: Actor,
+        fumble: bool,
+    },
+    /// Damage was dealt, after resistances
+    Damaged {
+        victim: Entity,
+        name: Option<String>,
+        /// Whoever or whatever dealt the damage, if anything
+        source: Option<Entity>,
+        amount: i32,
+        kind: Option<DamageType>,
+        resistance: Option<Resistance>,
+    },
+    Died {
+        entity: Entity,
+        name: String,
+        /// Whether it was one of the dungeon's monsters
+        monster: bool,
+    },
+    Moved {
+        entity: Entity,
+        to: Position,
+    },
+    ItemPickedUp {
+        by: Entity,
+        name: String,
+        gold: i32,
+    },
+    ItemUsed {
+        by: Entity,
+        name: String,
+        /// What using it takes, as in "you eat the ration"
+        verb: &'static str,
+    },
+    LevelEntered {
+        /// Name of the fixed level, `None` for a generated one
+        name: Option<String>,
+    },
+}
+
+/// Events published this turn and not yet dispatched
+#[derive(Debug, Default)]
+pub struct Events {
+    queue: Vec<Event>,
+    /// Every event dispatched since recording started
+    recorded: Option<Vec<Event>>,
+}
+
+impl Events {
+    pub fn publish(&mut self, event: Event) {
+        self.queue.push(event);
+    }
+
+    /// Keep a copy of every event from now on
+    pub fn record(&mut self) {
+        self.recorded.get_or_insert_with(Vec::new);
+    }
+
+    /// The events recorded since the last call
+    pub fn take_recorded(&mut self) -> Vec<Event> {
+        self.recorded
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+}
+
+/// Hand every published event to the subscribers, including those they publish themselves,
+/// and print them to the debug log when asked to on the command line
+pub fn dispatch(gs: &mut State) {
+    while !gs.events.queue.is_empty() {
+        let events = std::mem::take(&mut gs.events.queue);
+        for event in &events {
+            for subscriber in SUBSCRIBERS {
+                subscriber(gs, event);
+            }
+        }
+        if let Some(recorded) = &mut gs.events.recorded {
+            recorded.extend(events);
+        }
+    }
+    if gs.options.log_events {
+        log_recorded(gs);
+    }
+}
+
+fn log_recorded(gs: &mut State) {
+    for event in gs.events.take_recorded() {
+        console::log(format!("[event] turn {}: {event:?}", gs.turn));
+    }
+}
+
+/// Tell the player what happened
+fn log(gs: &mut State, event: &Event) {
+    let msg = match event {
+        Event::Attacked {
+            attacker,
+            target,
+            outcome,
+            damage,
+            kind,
+            blocked,
+        } => {
+            let verb = match outcome {
+                Outcome::Critical => "critically hits",
+                Outcome::SneakAttack => "sneak attacks",
+                _ => "hits",
+            };
+            format!(
+                "{} {verb} {} for {damage} {kind} [blocked {blocked}]",
+                attacker.name, target.name
+            )
+        }
+        Event::Missed {
+            attacker,
+            target,
+            fumble: true,
+        } => format!("{} fumbles the attack on {}", attacker.name, target.name),
+        Event::Missed {
+            attacker, target, ..
+        } => format!("{} misses {}", attacker.name, target.name),
+        Event::Damaged {
+            name: Some(name),
+            kind: Some(kind),
+            resistance: Some(resistance),
+            ..
+        } => match resistance {
+            Resistance::Resistant => format!("{name} resists the {kind} damage."),
+            Resistance::Immune => format!("{name} is immune to {kind} damage."),
+            Resistance::Vulnerable => format!("{name} is hurt badly by the {kind}!"),
+        },
+        Event::Died { entity, name, .. } if *entity == gs.player => {
+            format!("{name} died. Game over")
+        }
+        Event::Died { name, .. } => format!("{name} is dead."),
+        Event::Moved { entity, to } if *entity == gs.player => {
+            let items: Vec<String> = gs
+                .map
+                .index
+                .at(*to)
+                .iter()
+                .filter(|&&e| gs.world.satisfies::<&Item>(e).unwrap_or(false))
+                .filter(|&&e| !gs.world.satisfies::<&Hidden>(e).unwrap_or(false))
+                .filter_map(|&e| gs.world.get::<&Name>(e).ok().map(|name| name.to_string()))
+                .collect();
+            if items.is_empty() {
+                return;
+            }
+            format!("You see here: {}.", items.join(", "))
+        }
+        Event::ItemPickedUp { by, name, gold } if *by == gs.player => {
+            format!("You pick up the {name} ({gold} gold).")
+        }
+        Event::ItemUsed { by, name, verb } if *by == gs.player => {
+            format!("You {verb} the {name}.")
+        }
+        Event::LevelEntered { name: Some(name) } => format!("Welcome to the {name}."),
+        Event::LevelEntered { name: None } => "Welcome to the game.".into(),
+        _ => return,
+    };
+    gs.msg_log.push(msg);
+}
+
+/// Show melee attacks on the map
+fn animate(gs: &mut State, event: &Event) {
+    match event {
+        Event::Attacked {
+            attacker,
+            target,
+            damage,
+            ..
+        } => gs.animations.melee(attacker.pos, target.pos, *damage),
+        Event::Missed {
+            attacker, target, ..
+        } => gs.animations.miss(attacker.pos, target.pos),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bracket_lib::prelude::Point;
+
+    use super::*;
+    use crate::{
+        combat::{self, CombatStats, Percentage, WantsToMelee},
+        damage::{DamageType, Resistances},
+        formula::{Dice, Rules},
+        monster::Monster,
+        spawn, Options,
+    };
+
+    #[test]
+    fn melee_exchange_is_recorded() {
+        let options = Options {
+            rules: Rules::Simple,
+            ..Options::default()
+        };
+        let mut gs = State::new(1, options);
+        let start = gs.map.start;
+        gs.player = spawn::player(&mut gs.world, &mut gs.rng, &gs.options, start);
+        let stats = CombatStats {
+            max_hp: 10,
+            hp: 10,
+            accuracy: Percentage::new(1.0),
+            evasion: Percentage::new(0.0),
+            armor: 0,
+            damage: Dice::new(1, 4, 10),
+            damage_type: DamageType::Slashing,
+        };
+        _ = gs.world.insert_one(gs.player, stats);
+        let goblin = gs.world.spawn((
+            start + Point::new(1, 0),
+            Name::new("Goblin"),
+            Monster,
+            stats,
+            Resistances(vec![(DamageType::Slashing, Resistance::Vulnerable)]),
+        ));
+        gs.index_entities();
+        gs.events.record();
+
+        _ = gs
+            .world
+            .insert_one(gs.player, WantsToMelee { target: goblin });
+        combat::run(&mut gs);
+        dispatch(&mut gs);
+
+        let events = gs.events.take_recorded();
+        match events.as_slice() {
+            [Event::Attacked {
+                attacker,
+                target,
+                damage,
+                ..
+            }, Event::Damaged {
+                victim,
+                source,
+                amount,
+                ..
+            }, Event::Died {
+                entity,
+                monster: true,
+                ..
+            }] => {
+                assert_eq!((attacker.entity, target.entity), (gs.player, goblin));
+                assert_eq!((*victim, *source), (goblin, Some(gs.player)));
+                // the hit tells of the damage after the goblin's weakness to blades
+                assert!(*amount >= 22);
+                assert_eq!(*damage, *amount);
+                assert_eq!(*entity, goblin);
+            }
+            other => panic!("unexpected events: {other:?}"),
+        }
+        assert!(!gs.world.contains(goblin));
+        assert_eq!(gs.stats.total_kills(), 1);
+    }
+}
@@ -0,0 +1,338 @@
+use std::fmt;
+
+use bracket_lib::prelude::RandomNumberGenerator;
+
+use crate::combat::CombatStats;
+
+/// Rolls of 1d100 at or below this always miss
+const FUMBLE: i32 = 5;
+/// Rolls of 1d100 at or above this always hit, with double damage
+const CRITICAL: i32 = 96;
+/// Damage dice rolled when hitting someone who did not see it coming
+const SNEAK_ATTACK_ROLLS: i32 = 3;
+
+/// Damage expressed as `count`d`sides`+`bonus`, e.g. 1d6+2
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dice {
+    pub count: i32,
+    pub sides: i32,
+    pub bonus: i32,
+}
+
+impl Dice {
+    pub const fn new(count: i32, sides: i32, bonus: i32) -> Self {
+        Self {
+            count,
+            sides,
+            bonus,
+        }
+    }
+
+    pub fn roll(&self, rng: &mut RandomNumberGenerator) -> i32 {
+        rng.roll_dice(self.count, self.sides) + self.bonus
+    }
+}
+
+impl fmt::Display for Dice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}d{}", self.count, self.sides)?;
+        match self.bonus {
+            0 => Ok(()),
+            b if b > 0 => write!(f, "+{b}"),
+            b => write!(f, "{b}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Fumble,
+    Miss,
+    Hit,
+    Critical,
+    SneakAttack,
+}
+
+#[derive(Debug, Clone)]
+pub struct Attack {
+    pub outcome: Outcome,
+    /// Damage that gets through the armor
+    pub damage: i32,
+    /// Damage stopped by the armor
+    pub blocked: i32,
+    /// Every roll made, for tracing balance problems
+    pub rolls: Vec<String>,
+}
+
+/// A set of rules deciding how an attack plays out
+pub trait CombatFormula: Send + Sync {
+    /// `surprise` is set when the defender did not see the attack coming
+    fn attack(
+        &self,
+        rng: &mut RandomNumberGenerator,
+        attacker: &CombatStats,
+        defender: &CombatStats,
+        surprise: bool,
+    ) -> Attack;
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Rules {
+    /// Percentile to-hit roll with fumbles and critical hits
+    #[default]
+    Standard,
+    /// Percentile to-hit roll only
+    Simple,
+}
+
+impl Rules {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "standard" => Some(Rules::Standard),
+            "simple" => Some(Rules::Simple),
+            _ => None,
+        }
+    }
+
+    pub fn formula(self) -> Box<dyn CombatFormula> {
+        match self {
+            Rules::Standard => Box::new(StandardFormula),
+            Rules::Simple => Box::new(SimpleFormula),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct StandardFormula;
+
+impl CombatFormula for StandardFormula {
+    fn attack(
+        &self,
+        rng: &mut RandomNumberGenerator,
+        attacker: &CombatStats,
+        defender: &CombatStats,
+        surprise: bool,
+    ) -> Attack {
+        if surprise {
+            return sneak_attack(rng, attacker, defender);
+        }
+        let mut rolls = Vec::new();
+        let chance = hit_chance(attacker, defender);
+        let roll = rng.roll_dice(1, 100);
+        let outcome = standard_outcome(roll, chance);
+        rolls.push(format!("to hit: 1d100 = {roll} vs {chance} -> {outcome:?}"));
+        let (damage, blocked) = match outcome {
+            Outcome::Hit => roll_damage(rng, attacker, defender, 1, &mut rolls),
+            Outcome::Critical => roll_damage(rng, attacker, defender, 2, &mut rolls),
+            _ => (0, 0),
+        };
+        Attack {
+            outcome,
+            damage,
+            blocked,
+            rolls,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SimpleFormula;
+
+impl CombatFormula for SimpleFormula {
+    fn attack(
+        &self,
+        rng: &mut RandomNumberGenerator,
+        attacker: &CombatStats,
+        defender: &CombatStats,
+        surprise: bool,
+    ) -> Attack {
+        if surprise {
+            return sneak_attack(rng, attacker, defender);
+        }
+        let mut rolls = Vec::new();
+        let chance = hit_chance(attacker, defender);
+        let roll = rng.roll_dice(1, 100);
+        let outcome = simple_outcome(roll, chance);
+        rolls.push(format!("to hit: 1d100 = {roll} vs {chance} -> {outcome:?}"));
+        let (damage, blocked) = match outcome {
+            Outcome::Hit => roll_damage(rng, attacker, defender, 1, &mut rolls),
+            _ => (0, 0),
+        };
+        Attack {
+            outcome,
+            damage,
+            blocked,
+            rolls,
+        }
+    }
+}
+
+/// How a 1d100 to-hit roll turns out under the standard rules
+fn standard_outcome(roll: i32, chance: i32) -> Outcome {
+    match roll {
+        r if r <= FUMBLE => Outcome::Fumble,
+        r if r >= CRITICAL => Outcome::Critical,
+        r if r <= chance => Outcome::Hit,
+        _ => Outcome::Miss,
+    }
+}
+
+/// How a 1d100 to-hit roll turns out under the simple rules
+fn simple_outcome(roll: i32, chance: i32) -> Outcome {
+    if roll <= chance {
+        Outcome::Hit
+    } else {
+        Outcome::Miss
+    }
+}
+
+/// Percent chance to hit, the attacker's accuracy reduced by the defender's evasion
+fn hit_chance(attacker: &CombatStats, defender: &CombatStats) -> i32 {
+    let chance = attacker.accuracy.value() - defender.evasion.value();
+    ((chance * 100.0).round() as i32).clamp(0, 100)
+}
+
+fn sneak_attack(
+    rng: &mut RandomNumberGenerator,
+    attacker: &CombatStats,
+    defender: &CombatStats,
+) -> Attack {
+    let mut rolls = vec!["to hit: defender unaware -> SneakAttack".to_string()];
+    let (damage, blocked) = roll_damage(rng, attacker, defender, SNEAK_ATTACK_ROLLS, &mut rolls);
+    Attack {
+        outcome: Outcome::SneakAttack,
+        damage,
+        blocked,
+        rolls,
+    }
+}
+
+/// Roll the attacker's damage dice `times` times and take off the defender's armor once
+fn roll_damage(
+    rng: &mut RandomNumberGenerator,
+    attacker: &CombatStats,
+    defender: &CombatStats,
+    times: i32,
+    rolls: &mut Vec<String>,
+) -> (i32, i32) {
+    let dice = attacker.damage;
+    let raw: i32 = (0..times).map(|_| dice.roll(rng)).sum();
+    let damage = (raw - defender.armor).max(0);
+    rolls.push(format!(
+        "damage: {times} x {dice} = {raw}, armor {} -> {damage}",
+        defender.armor
+    ));
+    (damage, raw - damage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{combat::Percentage, damage::DamageType};
+
+    fn fighter(accuracy: f32, evasion: f32, armor: i32) -> CombatStats {
+        CombatStats {
+            max_hp: 10,
+            hp: 10,
+            accuracy: Percentage::new(accuracy),
+            evasion: Percentage::new(evasion),
+            armor,
+            damage: Dice::new(1, 6, 0),
+            damage_type: DamageType::Blunt,
+        }
+    }
+
+    #[test]
+    fn dice_stay_within_bounds() {
+        let mut rng = RandomNumberGenerator::seeded(1);
+        let dice = Dice::new(2, 6, 1);
+        let rolls: Vec<i32> = (0..1000).map(|_| dice.roll(&mut rng)).collect();
+        assert!(rolls.iter().all(|r| (3..=13).contains(r)));
+        assert!(rolls.contains(&3));
+        assert!(rolls.contains(&13));
+    }
+
+    #[test]
+    fn dice_display() {
+        assert_eq!(Dice::new(1, 6, 0).to_string(), "1d6");
+        assert_eq!(Dice::new(2, 4, 3).to_string(), "2d4+3");
+        assert_eq!(Dice::new(1, 8, -1).to_string(), "1d8-1");
+    }
+
+    #[test]
+    fn standard_boundaries() {
+        assert_eq!(standard_outcome(1, 100), Outcome::Fumble);
+        assert_eq!(standard_outcome(FUMBLE, 100), Outcome::Fumble);
+        assert_eq!(standard_outcome(FUMBLE + 1, 100), Outcome::Hit);
+        assert_eq!(standard_outcome(50, 50), Outcome::Hit);
+        assert_eq!(standard_outcome(51, 50), Outcome::Miss);
+        assert_eq!(standard_outcome(CRITICAL - 1, 100), Outcome::Hit);
+        assert_eq!(standard_outcome(CRITICAL - 1, 0), Outcome::Miss);
+        assert_eq!(standard_outcome(CRITICAL, 0), Outcome::Critical);
+        assert_eq!(standard_outcome(100, 0), Outcome::Critical);
+    }
+
+    #[test]
+    fn simple_boundaries() {
+        assert_eq!(simple_outcome(1, 100), Outcome::Hit);
+        assert_eq!(simple_outcome(1, 0), Outcome::Miss);
+        assert_eq!(simple_outcome(50, 50), Outcome::Hit);
+        assert_eq!(simple_outcome(51, 50), Outcome::Miss);
+        assert_eq!(simple_outcome(100, 100), Outcome::Hit);
+    }
+
+    #[test]
+    fn hit_chance_is_clamped() {
+        assert_eq!(
+            hit_chance(&fighter(0.65, 0.1, 0), &fighter(0.5, 0.0, 0)),
+            65
+        );
+        assert_eq!(
+            hit_chance(&fighter(0.65, 0.0, 0), &fighter(0.5, 0.1, 0)),
+            55
+        );
+        assert_eq!(hit_chance(&fighter(0.1, 0.0, 0), &fighter(0.5, 0.5, 0)), 0);
+    }
+
+    #[test]
+    fn standard_rules_outcomes() {
+        let mut rng = RandomNumberGenerator::seeded(2);
+        let formula = Rules::Standard.formula();
+        let (sure, armored) = (fighter(1.0, 0.0, 0), fighter(0.5, 0.0, 2));
+        for _ in 0..1000 {
+            let attack = formula.attack(&mut rng, &sure, &armored, false);
+            match attack.outcome {
+                Outcome::Fumble => assert_eq!((attack.damage, attack.blocked), (0, 0)),
+                Outcome::Hit => assert!((0..=4).contains(&attack.damage)),
+                Outcome::Critical => assert!((0..=10).contains(&attack.damage)),
+                outcome => panic!("unexpected {outcome:?} with a certain hit"),
+            }
+            assert_eq!(
+                attack.damage + attack.blocked > 0,
+                attack.outcome != Outcome::Fumble
+            );
+        }
+    }
+
+    #[test]
+    fn simple_rules_never_fumble_nor_crit() {
+        let mut rng = RandomNumberGenerator::seeded(3);
+        let formula = Rules::Simple.formula();
+        let (attacker, defender) = (fighter(0.5, 0.0, 0), fighter(0.5, 0.0, 0));
+        for _ in 0..1000 {
+            let attack = formula.attack(&mut rng, &attacker, &defender, false);
+            assert!(matches!(attack.outcome, Outcome::Hit | Outcome::Miss));
+        }
+    }
+
+    #[test]
+    fn surprise_always_sneak_attacks() {
+        let mut rng = RandomNumberGenerator::seeded(4);
+        let (attacker, defender) = (fighter(0.0, 0.0, 0), fighter(0.5, 1.0, 0));
+        for rules in [Rules::Standard, Rules::Simple] {
+            let attack = rules.formula().attack(&mut rng, &attacker, &defender, true);
+            assert_eq!(attack.outcome, Outcome::SneakAttack);
+            assert!((SNEAK_ATTACK_ROLLS..=6 * SNEAK_ATTACK_ROLLS).contains(&attack.damage));
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use std::fmt;
+
+use bracket_lib::prelude::*;
+
+use crate::{combat::SufferDamage, damage::Damage, event::Event, position::Position, Name, State};
+
+pub const MAX_FOOD: i32 = 2000;
+const WELL_FED: i32 = 1500;
+const HUNGRY: i32 = 400;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HungerState {
+    WellFed,
+    Normal,
+    Hungry,
+    Starving,
+}
+
+impl fmt::Display for HungerState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HungerState::WellFed => "Well Fed".fmt(f),
+            HungerState::Normal => "Normal".fmt(f),
+            HungerState::Hungry => "Hungry".fmt(f),
+            HungerState::Starving => "Starving".fmt(f),
+        }
+    }
+}
+
+impl HungerState {
+    pub fn color(self) -> RGB {
+        match self {
+            HungerState::WellFed => RGB::named(GREEN),
+            HungerState::Normal => RGB::named(WHITE),
+            HungerState::Hungry => RGB::named(ORANGE),
+            HungerState::Starving => RGB::named(RED),
+        }
+    }
+}
+
+/// Food left in the stomach, counts down every turn
+#[derive(Debug, Clone, Copy)]
+pub struct Hunger {
+    pub food: i32,
+}
+
+impl Hunger {
+    pub fn state(&self) -> HungerState {
+        match self.food {
+            f if f > WELL_FED => HungerState::WellFed,
+            f if f > HUNGRY => HungerState::Normal,
+            f if f > 0 => HungerState::Hungry,
+            _ => HungerState::Starving,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Food {
+    pub nutrition: i32,
+}
+
+pub fn run(gs: &mut State) {
+    if !gs.options.hunger {
+        return;
+    }
+    let mut starving = Vec::new();
+    for (e, hunger) in gs.world.query_mut::<&mut Hunger>() {
+        let before = hunger.state();
+        hunger.food = (hunger.food - gs.options.hunger_rate).max(0);
+        let after = hunger.state();
+        if e == gs.player && before != after {
+            match after {
+                HungerState::Normal => gs.msg_log.push("You are no longer well fed.".into()),
+                HungerState::Hungry => gs.msg_log.push("You are getting hungry.".into()),
+                HungerState::Starving => gs.msg_log.push("You are starving!".into()),
+                HungerState::WellFed => {}
+            }
+        }
+        if after == HungerState::Starving {
+            starving.push(e);
+        }
+    }
+    for e in starving {
+        SufferDamage::add_damage(&mut gs.world, e, Damage::untyped(1));
+    }
+}
+
+impl State {
+    /// Eat the first food item lying on the player's tile
+    pub fn eat(&mut self) -> bool {
+        let player_pos = *self.world.query_one_mut::<&Position>(self.player).unwrap();
+        let meal = self
+            .world
+            .query::<(&Position, &Food, &Name)>()
+            .iter()
+            .find(|(_, (pos, _, _))| **pos == player_pos)
+            .map(|(e, (_, food, name))| (e, food.nutrition, name.to_string()));
+
+        let Some((food, nutrition, name)) = meal else {
+            self.msg_log.push("There is nothing to eat here.".into());
+            return false;
+        };
+        if let Ok(hunger) = self.world.query_one_mut::<&mut Hunger>(self.player) {
+            hunger.food = (hunger.food + nutrition).min(MAX_FOOD);
+        }
+        self.despawn(food);
+        self.events.publish(Event::ItemUsed {
+            by: self.player,
+            name,
+            verb: "eat",
+        });
+        true
+    }
+}
@@ -0,0 +1,82 @@
+use bracket_lib::prelude::*;
+use grid::{Grid, Order};
+
+use crate::{position::Position, State};
+
+/// Light level below which a tile counts as dark
+const DARK: f32 = 0.15;
+/// How bright a tile in complete darkness is drawn, so that what you can touch stays readable
+const MIN_BRIGHTNESS: f32 = 0.35;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LightSource {
+    pub radius: i32,
+    pub color: RGB,
+}
+
+/// Rebuild the light map from lit rooms and light sources if any of them moved,
+/// returns the tiles that got brighter or darker
+pub fn compute_lighting(gs: &mut State) -> Vec<Position> {
+    let sources: Vec<(Position, LightSource)> = gs
+        .world
+        .query_mut::<(&Position, &LightSource)>()
+        .into_iter()
+        .map(|(_, (pos, source))| (*pos, *source))
+        .collect();
+    if gs.map.lights.as_ref() == Some(&sources) {
+        return Vec::new();
+    }
+
+    let mut light =
+        Grid::init_with_order(gs.map.width, gs.map.height, Order::ColumnMajor, RGB::new());
+    for (idx, tile) in light.indexed_iter_mut() {
+        if gs.map.lit[idx] {
+            *tile = RGB::from_f32(1.0, 1.0, 1.0);
+        }
+    }
+
+    for (pos, source) in &sources {
+        let center = Point::from(*pos);
+        for p in field_of_view_set(center, source.radius, &gs.map) {
+            let Ok(lit_pos) = Position::try_from(p) else {
+                continue;
+            };
+            let distance = DistanceAlg::Pythagoras.distance2d(center, p);
+            if !gs.map.in_bounds(p) || distance > source.radius as f32 {
+                continue;
+            }
+            let intensity = 1.0 - distance / (source.radius as f32 + 1.0);
+            let tile = &mut light[lit_pos.into()];
+            tile.r = (tile.r + source.color.r * intensity).min(1.0);
+            tile.g = (tile.g + source.color.g * intensity).min(1.0);
+            tile.b = (tile.b + source.color.b * intensity).min(1.0);
+        }
+    }
+
+    let changed = light
+        .flatten()
+        .iter()
+        .zip(gs.map.light.flatten())
+        .enumerate()
+        .filter(|(_, (new, old))| new != old)
+        .map(|(idx, _)| gs.map.to_pos(idx))
+        .collect();
+    gs.map.light = light;
+    gs.map.lights = Some(sources);
+    changed
+}
+
+/// Tint a color by the light falling on it
+pub fn shade(color: RGBA, light: RGB) -> RGBA {
+    let scale = |c: f32| MIN_BRIGHTNESS + (1.0 - MIN_BRIGHTNESS) * c;
+    RGBA::from_f32(
+        color.r * scale(light.r),
+        color.g * scale(light.g),
+        color.b * scale(light.b),
+        color.a,
+    )
+}
+
+pub fn is_bright(light: RGB) -> bool {
+    light.r.max(light.g).max(light.b) > DARK
+}
@@ -1,27 +1,73 @@
-use std::collections::{HashSet, VecDeque};
+use std::collections::{HashMap, HashSet};
 
+use crate::activity::{Activity, ActivityKind};
+use crate::animation::Animations;
 use crate::combat::{CombatStats, WantsToMelee};
+use crate::companion::{Command, Companion};
+use crate::event::{Event, Events};
+use crate::formula::{CombatFormula, Rules};
 use crate::map::{Map, TileType};
+use crate::movement::WantsToMove;
+use crate::noise::Noise;
 use crate::position::Position;
+use crate::statistics::{Run, SortBy, Statistics};
+use crate::status::Confused;
+use crate::trap::Hidden;
+use crate::wizard::Wizard;
 use bracket_lib::pathfinding::Algorithm2D;
 use bracket_lib::prelude::*;
 use hecs::{Entity, World};
 
+mod ability;
+mod activity;
+mod animation;
+mod boss;
 mod combat;
+mod companion;
+mod connectivity;
+mod corpse;
+mod damage;
+mod event;
+mod formula;
+mod hunger;
+mod light;
 mod map;
+mod memory;
 mod monster;
+mod movement;
+mod noise;
 mod position;
+mod prefab;
+mod room;
+mod spatial;
 mod spawn;
+mod statistics;
+mod status;
+mod stealth;
+mod trap;
 mod ui;
+mod wizard;
 
 const CONSOLE_WIDTH: i32 = 60;
 const CONSOLE_HEIGHT: i32 = 42;
 const UI_HEIGHT: i32 = 10;
 
+/// Draw order of entities sharing a tile, later layers are drawn on top
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RenderOrder {
+    Decal,
+    Item,
+    Corpse,
+    Creature,
+    Player,
+    Effect,
+}
+
 #[derive(Debug)]
 struct Renderable {
     glyph: FontCharType,
     colors: ColorPair,
+    order: RenderOrder,
 }
 
 #[derive(Debug)]
@@ -40,6 +86,47 @@ impl std::fmt::Display for Name {
 #[derive(Debug)]
 struct Player;
 
+#[derive(Debug)]
+pub struct Item;
+
+/// Game settings chosen on the command line
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Whether the player has to eat
+    pub hunger: bool,
+    /// Food used up every turn
+    pub hunger_rate: i32,
+    pub rules: Rules,
+    /// Print every combat roll to the debug log
+    pub log_rolls: bool,
+    /// Print every gameplay event to the debug log
+    pub log_events: bool,
+    /// Whether to play animations at all
+    pub animations: bool,
+    /// Animation frames played per tick
+    pub animation_speed: f32,
+    /// Fixed level to play instead of a generated one
+    pub level: Option<String>,
+    /// Enable the debug console
+    pub wizard: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            hunger: true,
+            hunger_rate: 1,
+            rules: Rules::default(),
+            log_rolls: false,
+            log_events: false,
+            animations: true,
+            animation_speed: 1.0,
+            level: None,
+            wizard: false,
+        }
+    }
+}
+
 #[derive(Debug)]
 struct BlocksTile;
 
@@ -75,71 +162,18 @@ pub enum Phase {
     MonsterTurn,
     Rendering,
     Animating,
-}
-
-#[derive(Debug, Clone)]
-pub enum AnimationKind {
-    Miss {
-        src_pos: Position,
-        dst_pos: Position,
+    /// Choosing where to aim one of the player's abilities
+    Targeting {
+        ability: usize,
     },
-    MeleeDmg {
-        src_pos: Position,
-        dst_pos: Position,
-        dmg: i32,
+    /// The player is dead, showing how the run went
+    GameOver,
+    /// Looking through past runs
+    History {
+        sort: SortBy,
     },
-}
-
-#[derive(Debug, Clone)]
-pub struct Animation {
-    duration: i8,
-    kind: AnimationKind,
-}
-
-impl Animation {
-    pub fn miss(src_pos: Position, dst_pos: Position) -> Self {
-        Self {
-            kind: AnimationKind::Miss { src_pos, dst_pos },
-            duration: 8,
-        }
-    }
-    pub fn melee(src_pos: Position, dst_pos: Position, dmg: i32) -> Self {
-        Self {
-            kind: AnimationKind::MeleeDmg {
-                src_pos,
-                dst_pos,
-                dmg,
-            },
-            duration: 8,
-        }
-    }
-    /// Advance the animation and indicate if it is finished
-    fn advance(&mut self, ctx: &mut BTerm) -> bool {
-        match self.kind {
-            AnimationKind::Miss { src_pos, dst_pos } => {
-                if self.duration > 6 {
-                    ctx.print_color(src_pos.x, src_pos.y, WHITE, BLACK, ' ');
-                }
-                if self.duration > 3 {
-                    ctx.print_color(dst_pos.x, dst_pos.y, WHITE, BLACK, '/');
-                }
-            }
-            AnimationKind::MeleeDmg {
-                src_pos,
-                dst_pos,
-                dmg,
-            } => {
-                if self.duration > 6 {
-                    ctx.print_color(src_pos.x, src_pos.y, WHITE, BLACK, ' ');
-                }
-                if self.duration > 3 {
-                    ctx.print_color(dst_pos.x, dst_pos.y, WHITE, BLACK, dmg);
-                }
-            }
-        }
-        self.duration -= 1;
-        self.duration < 0
-    }
+    /// Typing a command into the wizard console
+    Console,
 }
 
 struct State {
@@ -147,11 +181,27 @@ struct State {
     map: Map,
     rng: RandomNumberGenerator,
     dm: DijkstraMap,
+    /// Tiles of the traps the monsters have learned about, which they path around
+    known_traps: HashSet<usize>,
     phase: Phase,
     player: Entity,
+    /// Number of turns since the start of the game
+    turn: u32,
+    options: Options,
+    formula: Box<dyn CombatFormula>,
     msg_log: Vec<String>,
-    animation_queue: VecDeque<Animation>,
-    current_animation: Option<Animation>,
+    activity: Option<Activity>,
+    noises: Vec<Noise>,
+    /// Loudness of the last propagated sounds on each tile, for the debug overlay
+    sound: Vec<f32>,
+    show_sound: bool,
+    animations: Animations,
+    events: Events,
+    stats: Statistics,
+    /// Past runs shown on the history screen
+    runs: Vec<Run>,
+    /// Debug tools, only there in wizard mode
+    wizard: Option<Wizard>,
 }
 
 impl GameState for State {
@@ -159,17 +209,37 @@ impl GameState for State {
         loop {
             match self.phase {
                 Phase::Startup => {
+                    self.index_entities();
+                    wizard::run_script(self);
                     self.compute_visibility();
-                    self.update_map();
-                    self.msg_log.push("Welcome to the game.".into());
+                    let name = self.options.level.clone();
+                    self.events.publish(Event::LevelEntered { name });
+                    event::dispatch(self);
                     self.phase = Phase::Rendering;
                 }
                 Phase::AwaitingInput => {
-                    if self.player_input(ctx) {
+                    let took_turn = if self.activity.is_some() {
+                        // any key press or click cancels what the player is doing
+                        if ctx.key.is_some() || ctx.left_click {
+                            self.stop_activity();
+                            false
+                        } else {
+                            self.continue_activity()
+                        }
+                    } else {
+                        self.player_input(ctx)
+                    };
+                    if took_turn {
                         self.phase = Phase::PlayerTurn;
                     } else {
                         ctx.set_active_console(1);
                         ctx.cls();
+                        self.draw_awareness(ctx);
+                        self.draw_tooltip(ctx);
+                        if self.show_sound {
+                            self.draw_sound_overlay(ctx);
+                        }
+                        self.draw_wizard_overlays(ctx);
                         if ctx.control {
                             for (idx, tile) in self.map.tiles.iter().enumerate() {
                                 let d = self.dm.map[idx];
@@ -183,21 +253,46 @@ impl GameState for State {
                     }
                 }
                 Phase::PlayerTurn => {
+                    movement::run(self);
+                    event::dispatch(self);
                     self.compute_visibility();
                     self.compute_dijkstra_map();
+                    trap::perceive(self);
+                    room::announce(self);
+                    ability::run(self);
                     combat::run(self);
-                    self.update_map();
-                    self.phase = Phase::MonsterTurn;
+                    event::dispatch(self);
+                    self.animations.end_group();
+                    // a dead player gets no more turns, but still sees the final blow
+                    self.phase = if self.world.contains(self.player) {
+                        Phase::MonsterTurn
+                    } else {
+                        Phase::Rendering
+                    };
                 }
                 Phase::MonsterTurn => {
                     self.compute_visibility();
                     self.compute_dijkstra_map();
+                    noise::propagate(self);
+                    boss::run(self);
                     monster::apply_ai(self);
+                    companion::run(self);
+                    movement::run(self);
+                    event::dispatch(self);
+                    hunger::run(self);
+                    ability::run(self);
                     combat::run(self);
-                    self.update_map();
+                    event::dispatch(self);
+                    combat::regenerate(self);
+                    ability::recover(self);
+                    status::run(self);
+                    corpse::decay(self);
+                    self.animations.end_group();
+                    self.turn += 1;
                     self.phase = Phase::Rendering;
                 }
                 Phase::Rendering => {
+                    self.remember_entities();
                     self.render(ctx);
                     self.draw_ui(ctx);
                     self.phase = Phase::Animating;
@@ -205,76 +300,194 @@ impl GameState for State {
                 Phase::Animating => {
                     ctx.set_active_console(1);
                     ctx.cls();
-                    if self.current_animation.is_none() {
-                        self.current_animation = self.animation_queue.pop_front();
+                    self.draw_awareness(ctx);
+                    // a key press skips whatever is left to play
+                    if !self.options.animations || ctx.key.is_some() {
+                        self.animations.skip();
+                    }
+                    let offset = self.camera_offset();
+                    let speed = self.options.animation_speed;
+                    if self.animations.advance(ctx, offset, speed) {
+                        self.phase = if self.world.contains(self.player) {
+                            Phase::AwaitingInput
+                        } else {
+                            self.finish_run();
+                            Phase::GameOver
+                        };
                     }
-                    if let Some(anim) = &mut self.current_animation {
-                        let finished = anim.advance(ctx);
-                        if finished {
-                            self.current_animation = None;
+                    break;
+                }
+                Phase::GameOver => {
+                    match ctx.key {
+                        Some(VirtualKeyCode::H) => {
+                            self.load_runs(SortBy::default());
+                            self.phase = Phase::History {
+                                sort: SortBy::default(),
+                            };
+                            ctx.key = None;
+                            continue;
                         }
-                    } else {
-                        self.phase = Phase::AwaitingInput;
+                        Some(VirtualKeyCode::Escape) => ctx.quit(),
+                        _ => {}
                     }
+                    self.draw_game_over(ctx);
                     break;
                 }
+                Phase::History { sort } => {
+                    match ctx.key {
+                        Some(VirtualKeyCode::Tab) => {
+                            let sort = sort.next();
+                            self.load_runs(sort);
+                            self.phase = Phase::History { sort };
+                            ctx.key = None;
+                            continue;
+                        }
+                        Some(VirtualKeyCode::Escape) => {
+                            self.phase = Phase::GameOver;
+                            ctx.key = None;
+                            continue;
+                        }
+                        _ => {}
+                    }
+                    self.draw_history(ctx, sort);
+                    break;
+                }
+                Phase::Console => {
+                    if !self.console_input(ctx) {
+                        self.draw_console(ctx);
+                        break;
+                    }
+                }
+                Phase::Targeting { ability } => match self.target_ability(ctx, ability) {
+                    Some(true) => self.phase = Phase::PlayerTurn,
+                    Some(false) => self.phase = Phase::AwaitingInput,
+                    None => {
+                        self.draw_targeting(ctx, ability);
+                        break;
+                    }
+                },
             }
         }
     }
 }
 
 impl State {
-    pub fn new(mut rng: RandomNumberGenerator) -> Self {
-        let map = Map::new(
+    pub fn new(seed: u64, options: Options) -> Self {
+        let mut rng = RandomNumberGenerator::seeded(seed);
+        let (width, height) = (
             CONSOLE_WIDTH as usize,
             (CONSOLE_HEIGHT - UI_HEIGHT) as usize,
-            &mut rng,
         );
+        let map = match options.level.as_deref().and_then(prefab::level) {
+            Some(level) => Map::from_prefab(&level, width, height),
+            None => Map::new(width, height, &mut rng),
+        };
+
+        let mut events = Events::default();
+        if options.log_events {
+            events.record();
+        }
+        let wizard = options.wizard.then(Wizard::default);
 
         Self {
             dm: DijkstraMap::new_empty(map.width, map.height, 100.0),
+            known_traps: Default::default(),
             map,
             rng,
             world: Default::default(),
             phase: Default::default(),
             player: Entity::DANGLING,
+            turn: 0,
+            formula: options.rules.formula(),
+            options,
             msg_log: Default::default(),
-            animation_queue: Default::default(),
-            current_animation: None,
+            activity: None,
+            noises: Default::default(),
+            sound: Default::default(),
+            show_sound: false,
+            animations: Default::default(),
+            events,
+            stats: Statistics::new(seed),
+            runs: Vec::new(),
+            wizard,
         }
     }
 
     fn player_input(&mut self, ctx: &BTerm) -> bool {
         use VirtualKeyCode as Key;
-        match ctx.key {
-            Some(Key::H | Key::A | Key::Left) => self.try_move_player(-1, 0),
-            Some(Key::J | Key::S | Key::Down) => self.try_move_player(0, 1),
-            Some(Key::K | Key::W | Key::Up) => self.try_move_player(0, -1),
-            Some(Key::L | Key::D | Key::Right) => self.try_move_player(1, 0),
-            _ => false,
+        if ctx.left_click {
+            return self.travel_to(ctx.mouse_point() - self.camera_offset());
+        }
+        let (dx, dy) = match ctx.key {
+            Some(Key::H | Key::A | Key::Left) => (-1, 0),
+            Some(Key::J | Key::S | Key::Down) => (0, 1),
+            Some(Key::K | Key::W | Key::Up) => (0, -1),
+            Some(Key::L | Key::D | Key::Right) => (1, 0),
+            Some(Key::O) => return self.start_activity(ActivityKind::Explore),
+            Some(Key::R) => return self.start_activity(ActivityKind::Rest),
+            Some(Key::Period | Key::Numpad5) => return true,
+            Some(Key::E) => return self.eat(),
+            Some(Key::F) => return self.search(),
+            Some(Key::G) => return self.loot(),
+            Some(Key::Key1) => return self.select_ability(0),
+            Some(Key::Key2) => return self.select_ability(1),
+            Some(Key::Key3) => return self.select_ability(2),
+            Some(Key::Key4) => return self.select_ability(3),
+            Some(Key::Key5) => return self.select_ability(4),
+            Some(Key::Key6) => return self.select_ability(5),
+            Some(Key::T) => return self.recruit(),
+            Some(Key::P) => return self.pray(),
+            Some(Key::Z) => return self.command_companions(Command::Stay),
+            Some(Key::X) => return self.command_companions(Command::Follow),
+            Some(Key::V) => return self.order_attack(ctx),
+            Some(Key::Grave) if self.wizard.is_some() => return self.open_console(),
+            Some(Key::F2) => {
+                self.show_sound = !self.show_sound;
+                return false;
+            }
+            _ => return false,
+        };
+        if ctx.shift {
+            self.start_activity(ActivityKind::run(dx, dy))
+        } else {
+            self.try_move_player(dx as i8, dy as i8)
         }
     }
 
-    fn try_move_player(&mut self, dx: i8, dy: i8) -> bool {
-        let mut moved = false;
-        let mut target = None;
+    fn travel_to(&mut self, target: Point) -> bool {
+        match Position::try_from(target) {
+            Ok(target)
+                if self.map.in_bounds(target.into())
+                    && self.map.revealed[target.into()]
+                    && self.map.tiles[target.into()] == TileType::Floor =>
+            {
+                self.start_activity(ActivityKind::Travel { target })
+            }
+            _ => false,
+        }
+    }
 
-        type Q<'w> = (&'w mut Position, &'w mut ViewShed);
-        for (_, (pos, fov)) in self.world.query::<Q>().with::<&Player>().iter() {
-            let new_pos = *pos + Point::new(dx, dy);
+    fn try_move_player(&mut self, mut dx: i8, mut dy: i8) -> bool {
+        if self
+            .world
+            .satisfies::<&Confused>(self.player)
+            .unwrap_or(false)
+            && self.rng.rand()
+        {
+            let dir = monster::random_direction(&mut self.rng);
+            (dx, dy) = (dir.x as i8, dir.y as i8);
+        }
 
-            for e in &self.map.entities[new_pos.into()] {
-                if let Ok(true) = self.world.satisfies::<&CombatStats>(*e) {
-                    target = Some(*e);
-                    moved = true;
-                }
-            }
+        let player_pos = *self.world.query_one_mut::<&Position>(self.player).unwrap();
+        let new_pos = player_pos + Point::new(dx, dy);
 
-            if self.map.is_passable(new_pos) {
-                self.map.blocked[pos.into()] = false;
-                *pos = new_pos;
-                fov.dirty = true;
-                moved = true;
+        let mut target = None;
+        let mut swap = None;
+        for &e in self.map.index.at(new_pos) {
+            if let Ok(true) = self.world.satisfies::<&Companion>(e) {
+                swap = Some(e);
+            } else if let Ok(true) = self.world.satisfies::<&CombatStats>(e) {
+                target = Some(e);
             }
         }
 
@@ -282,12 +495,39 @@ impl State {
             self.world
                 .insert_one(self.player, WantsToMelee { target })
                 .expect("Player exists");
+            return true;
         }
-
-        moved
+        if let Some(companion) = swap {
+            // companions step aside by trading places
+            let wants = WantsToMove {
+                destination: player_pos,
+            };
+            _ = self.world.insert_one(companion, wants);
+        } else if !self.map.is_passable(new_pos) {
+            return false;
+        }
+        self.world
+            .insert_one(
+                self.player,
+                WantsToMove {
+                    destination: new_pos,
+                },
+            )
+            .expect("Player exists");
+        true
     }
 
     fn compute_visibility(&mut self) {
+        let relit = light::compute_lighting(self);
+        if !relit.is_empty() {
+            // only those who could see a tile that got brighter or darker look again
+            for (_, (fov, pos)) in self.world.query_mut::<(&mut ViewShed, &Position)>() {
+                fov.dirty |= relit.iter().any(|&p| {
+                    DistanceAlg::Manhattan.distance2d(pos.into(), p.into()) <= fov.range as f32
+                });
+            }
+        }
+
         for (e, (fov, pos)) in self
             .world
             .query_mut::<(&mut ViewShed, &Position)>()
@@ -300,6 +540,11 @@ impl State {
                 .filter(|&p| self.map.in_bounds(p))
                 .filter(|&p| DistanceAlg::Manhattan.distance2d(pos.into(), p) <= fov.range as f32)
                 .filter_map(|p| Position::try_from(&p).ok())
+                // in the dark only what is right next to you can be seen
+                .filter(|&p| {
+                    self.map.is_lit(p)
+                        || DistanceAlg::Chebyshev.distance2d(pos.into(), p.into()) <= 1.0
+                })
                 .collect();
 
             if e == self.player {
@@ -312,34 +557,67 @@ impl State {
         }
     }
 
+    /// Where the map's origin is drawn on screen, the map fits the console so it never scrolls yet
+    fn camera_offset(&self) -> Point {
+        Point::zero()
+    }
+
     fn render(&mut self, ctx: &mut BTerm) {
         let mut draw_batch = DrawBatch::new();
         draw_batch.target(0);
         draw_batch.cls();
 
         self.map.draw(&mut draw_batch);
+        self.draw_remembered(&mut draw_batch);
 
-        for (_, (pos, render)) in self.world.query_mut::<(&Position, &Renderable)>() {
-            if self.map.visible[pos.into()] {
-                draw_batch.set(pos.into(), render.colors, render.glyph);
+        let mut drawables: Vec<_> = self
+            .world
+            .query_mut::<(&Position, &Renderable)>()
+            .without::<&Hidden>()
+            .into_iter()
+            .filter(|(_, (pos, _))| self.map.visible[(*pos).into()])
+            .map(|(_, (pos, render))| (*pos, render.order, render.colors, render.glyph))
+            .collect();
+        drawables.sort_by_key(|&(_, order, _, _)| order);
+
+        let mut stacked = HashMap::<Position, usize>::new();
+        for (pos, ..) in &drawables {
+            *stacked.entry(*pos).or_default() += 1;
+        }
+        for (pos, _, mut colors, glyph) in drawables {
+            if stacked[&pos] > 1 {
+                colors.bg = RGBA::from_f32(0.0, 0.0, 0.4, 1.0);
             }
+            draw_batch.set(pos.into(), colors, glyph);
         }
 
         draw_batch.submit(0).expect("Draw Batch");
         render_draw_buffer(ctx).expect("Render Buffer");
     }
 
-    fn update_map(&mut self) {
-        let mut blocked = self.map.new_grid();
-        for (idx, entry) in blocked.iter_mut().enumerate() {
-            *entry = self.map.is_opaque(idx);
-        }
-        self.map.clear_entities();
-        for (e, pos) in self.world.query_mut::<&Position>().with::<&BlocksTile>() {
-            blocked[pos.into()] = true;
-            self.map.entities[pos.into()].push(e);
+    /// Index every entity on the level from scratch, after that the index is kept up to date
+    /// as entities spawn, move and despawn
+    fn index_entities(&mut self) {
+        let entities: Vec<(Entity, Position, bool)> = self
+            .world
+            .query_mut::<(&Position, Option<&BlocksTile>)>()
+            .into_iter()
+            .map(|(e, (pos, blocks))| (e, *pos, blocks.is_some()))
+            .collect();
+        self.map.index.rebuild(entities);
+    }
+
+    /// Add an entity spawned or dropped during play to the index
+    fn place(&mut self, e: Entity) {
+        if let Ok(pos) = self.world.get::<&Position>(e) {
+            let blocks = self.world.satisfies::<&BlocksTile>(e).unwrap_or(false);
+            self.map.index.insert(e, *pos, blocks);
         }
-        self.map.blocked = blocked;
+    }
+
+    fn despawn(&mut self, e: Entity) {
+        self.map.index.remove(e);
+        _ = self.world.despawn(e);
     }
 
     fn compute_dijkstra_map(&mut self) {
@@ -349,7 +627,10 @@ impl State {
             self.map.width,
             self.map.height,
             &[player_pos],
-            &self.map,
+            &monster::Wary {
+                map: &self.map,
+                traps: &self.known_traps,
+            },
             100.0,
         );
         // have to manually set player position to <1.0, as we use this dijkstra map
@@ -358,20 +639,99 @@ impl State {
     }
 }
 
+/// Spawn the player and everything else living on the level
+fn populate(gs: &mut State) {
+    gs.player = spawn::player(&mut gs.world, &mut gs.rng, &gs.options, gs.map.start);
+
+    for (room, kind) in gs.map.rooms.iter().zip(&gs.map.room_kinds).skip(1) {
+        room::fill(&mut gs.world, &mut gs.rng, &gs.options, *room, *kind);
+    }
+    prefab::spawn_entities(&mut gs.world, &mut gs.rng, &gs.map);
+}
+
+fn usage(program: &str, code: i32) -> ! {
+    eprintln!(
+        "usage: {program} [--no-hunger] [--hunger-rate N] [--rules standard|simple] \
+         [--log-rolls] [--log-events] [--no-animations] [--animation-speed X] \
+         [--level NAME] [--map-stats] [--dump-map SEED] [--wizard] [--script FILE] [SEED]"
+    );
+    std::process::exit(code)
+}
+
 fn main() -> BError {
-    let args: Vec<String> = std::env::args().collect();
-    let seed = if args.len() == 2 {
-        u64::from_str_radix(&args[1], 16).unwrap_or_else(|_| {
-            eprintln!("usage: {} [SEED]", args[0]);
-            if args[1] == "--help" {
-                std::process::exit(0)
-            } else {
-                std::process::exit(1)
+    let mut args = std::env::args();
+    let program = args.next().unwrap_or_default();
+
+    let mut options = Options::default();
+    let mut seed = None;
+    let mut map_stats = false;
+    let mut dump_map = None;
+    let mut script = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--help" => usage(&program, 0),
+            "--no-hunger" => options.hunger = false,
+            "--hunger-rate" => {
+                options.hunger_rate = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .filter(|&rate: &i32| rate >= 0)
+                    .unwrap_or_else(|| usage(&program, 1))
+            }
+            "--rules" => {
+                options.rules = args
+                    .next()
+                    .and_then(|name| Rules::parse(&name))
+                    .unwrap_or_else(|| usage(&program, 1))
+            }
+            "--log-rolls" => options.log_rolls = true,
+            "--log-events" => options.log_events = true,
+            "--no-animations" => options.animations = false,
+            "--map-stats" => map_stats = true,
+            "--wizard" => options.wizard = true,
+            "--script" => {
+                let path = args.next().unwrap_or_else(|| usage(&program, 1));
+                script = match std::fs::read_to_string(&path) {
+                    Ok(text) => Some(text),
+                    Err(err) => {
+                        eprintln!("Cannot read {path}: {err}");
+                        std::process::exit(1)
+                    }
+                };
+                options.wizard = true;
+            }
+            "--dump-map" => {
+                dump_map = args
+                    .next()
+                    .and_then(|seed| u64::from_str_radix(&seed, 16).ok())
+                    .map(Some)
+                    .unwrap_or_else(|| usage(&program, 1))
+            }
+            "--level" => {
+                options.level = args
+                    .next()
+                    .filter(|name| prefab::level(name).is_some())
+                    .map(Some)
+                    .unwrap_or_else(|| usage(&program, 1))
+            }
+            "--animation-speed" => {
+                options.animation_speed = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .filter(|&speed: &f32| speed > 0.0)
+                    .unwrap_or_else(|| usage(&program, 1))
             }
-        })
-    } else {
-        RandomNumberGenerator::new().rand()
-    };
+            _ => seed = Some(u64::from_str_radix(&arg, 16).unwrap_or_else(|_| usage(&program, 1))),
+        }
+    }
+    if let Some(seed) = dump_map {
+        let mut gs = State::new(seed, options);
+        populate(&mut gs);
+        let entities = prefab::placed_entities(&gs.world);
+        print!("{}", gs.map.to_text(&format!("{seed:016x}"), &entities));
+        return Ok(());
+    }
+    let seed = seed.unwrap_or_else(|| RandomNumberGenerator::new().rand());
     eprintln!("SEED: {seed:016x}");
 
     let bterm = BTermBuilder::new()
@@ -382,17 +742,14 @@ fn main() -> BError {
         .with_tile_dimensions(16, 16)
         .build()?;
 
-    let rng = RandomNumberGenerator::seeded(seed);
-    let mut gs = State::new(rng);
-
-    gs.player = spawn::player(
-        &mut gs.world,
-        &mut gs.rng,
-        Position::try_from(&gs.map.rooms[0].center()).unwrap(),
-    );
+    let mut gs = State::new(seed, options);
+    if map_stats {
+        eprintln!("{}", gs.map.stats());
+    }
 
-    for room in &gs.map.rooms[1..] {
-        spawn::fill_room(&mut gs.world, &mut gs.rng, *room);
+    populate(&mut gs);
+    if let (Some(wizard), Some(text)) = (&mut gs.wizard, script) {
+        wizard.load_script(&text);
     }
 
     main_loop(bterm, gs)
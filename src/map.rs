@@ -1,8 +1,20 @@
+use std::collections::HashMap;
+
 use bracket_lib::prelude::*;
+use bracket_lib::terminal::console;
 use grid::{Grid, Order};
 use hecs::Entity;
 
-use crate::position::Position;
+use crate::{
+    light::{self, LightSource},
+    memory::Remembered,
+    position::Position,
+    room::RoomKind,
+    spatial::SpatialIndex,
+};
+
+/// Levels generated before giving up on getting a valid one
+const MAX_ATTEMPTS: u32 = 10;
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum TileType {
@@ -16,9 +28,25 @@ pub struct Map {
     pub tiles: Grid<TileType>,
     pub revealed: Grid<bool>,
     pub visible: Grid<bool>,
-    pub blocked: Grid<bool>,
-    pub entities: Grid<Vec<Entity>>,
+    /// Tiles lit by the dungeon itself, independent of light sources
+    pub lit: Grid<bool>,
+    /// Light falling on each tile, black is complete darkness
+    pub light: Grid<RGB>,
+    /// Light sources `light` was last built from, `None` before it first is
+    pub lights: Option<Vec<(Position, LightSource)>>,
+    /// Entities standing on the level
+    pub index: SpatialIndex,
+    /// What the player last saw of each entity on this level
+    pub remembered: HashMap<Entity, Remembered>,
     pub rooms: Vec<Rect>,
+    /// Where vaults were stamped, later tunnels are carved around them
+    pub vaults: Vec<Rect>,
+    /// Kind of each room in `rooms`
+    pub room_kinds: Vec<RoomKind>,
+    /// Where the player enters the level
+    pub start: Position,
+    /// Entities asked for by prefabs, spawned along with the rest of the level
+    pub spawns: Vec<(Position, String)>,
     pub width: usize,
     pub height: usize,
 }
@@ -29,9 +57,16 @@ impl Default for Map {
             tiles: Grid::new(0, 0),
             revealed: Grid::new(0, 0),
             visible: Grid::new(0, 0),
-            blocked: Grid::new(0, 0),
+            lit: Grid::new(0, 0),
+            light: Grid::init(0, 0, RGB::new()),
+            lights: None,
             rooms: Default::default(),
-            entities: Grid::new(0, 0),
+            vaults: Default::default(),
+            room_kinds: Default::default(),
+            start: Default::default(),
+            spawns: Default::default(),
+            index: SpatialIndex::default(),
+            remembered: Default::default(),
             width: 0,
             height: 0,
         }
@@ -39,7 +74,7 @@ impl Default for Map {
 }
 
 impl Map {
-    fn empty(width: usize, height: usize) -> Self {
+    pub fn empty(width: usize, height: usize) -> Self {
         let mut map = Map {
             width,
             height,
@@ -48,12 +83,33 @@ impl Map {
         map.tiles = map.new_grid();
         map.revealed = map.new_grid();
         map.visible = map.new_grid();
-        map.blocked = map.new_grid();
-        map.entities = map.new_grid();
+        map.lit = map.new_grid();
+        map.light = Grid::init_with_order(width, height, Order::ColumnMajor, RGB::new());
+        map.index = SpatialIndex::new(width, height);
         map
     }
 
+    /// Generate levels until one passes validation
     pub fn new(width: usize, height: usize, rng: &mut RandomNumberGenerator) -> Self {
+        let mut attempt = 1;
+        loop {
+            let mut map = Map::generate(width, height, rng);
+            map.connect_regions();
+            match map.validate() {
+                Ok(()) => return map,
+                Err(err) if attempt < MAX_ATTEMPTS => {
+                    console::log(format!("Rejected level {attempt}: {err}"));
+                    attempt += 1;
+                }
+                Err(err) => {
+                    console::log(format!("Keeping invalid level: {err}"));
+                    return map;
+                }
+            }
+        }
+    }
+
+    fn generate(width: usize, height: usize, rng: &mut RandomNumberGenerator) -> Self {
         let mut map = Map::empty(width, height);
 
         const MAX_ROOMS: i32 = 30;
@@ -69,6 +125,9 @@ impl Map {
             let new_room = Rect::with_size(x, y, w, h);
             if !map.rooms.iter().any(|room| room.intersect(&new_room)) {
                 map.carve_room(new_room);
+                if rng.roll_dice(1, 3) > 1 {
+                    map.light_room(new_room);
+                }
 
                 if let Some(prev_room) = map.rooms.last() {
                     let new = new_room.center();
@@ -86,8 +145,13 @@ impl Map {
             }
         }
 
-        for (idx, blocked) in map.blocked.indexed_iter_mut() {
-            *blocked = map.tiles[idx] == TileType::Wall;
+        map.room_kinds = RoomKind::pick(&map.rooms, rng);
+        for (room, kind) in map.rooms.clone().into_iter().zip(map.room_kinds.clone()) {
+            map.shape_room(room, kind);
+        }
+        map.place_vaults(rng);
+        if let Some(first) = map.rooms.first() {
+            map.start = Position::from_point(first.center());
         }
 
         map
@@ -107,7 +171,11 @@ impl Map {
             draw_batch.set(
                 Point::new(x, y),
                 ColorPair {
-                    fg: if self.visible[idx] { fg } else { RGBA::named(GREY40) },
+                    fg: if self.visible[idx] {
+                        light::shade(fg, self.light[idx])
+                    } else {
+                        RGBA::named(GREY40)
+                    },
                     bg: RGBA::named(BLACK),
                 },
                 glyph,
@@ -118,14 +186,27 @@ impl Map {
     pub fn is_passable(&self, p: impl Into<Point>) -> bool {
         let p = p.into();
         if self.in_bounds(p) {
-            let idx2d = Position::from_point(p).into();
             let idx = self.point2d_to_index(p);
-            !self.is_opaque(idx) && !self.blocked[idx2d]
+            !self.is_opaque(idx) && !self.index.is_blocked(Position::from_point(p))
         } else {
             false
         }
     }
 
+    /// Whether there is enough light to see a tile from afar
+    pub fn is_lit(&self, p: Position) -> bool {
+        light::is_bright(self.light[p.into()])
+    }
+
+    pub fn random_floor(&self, rng: &mut RandomNumberGenerator) -> Position {
+        loop {
+            let p = Point::new(rng.range(0, self.width), rng.range(0, self.height));
+            if self.is_passable(p) {
+                return Position::from_point(p);
+            }
+        }
+    }
+
     pub fn new_grid<T: Default>(&self) -> Grid<T> {
         Grid::new_with_order(self.width, self.height, Order::ColumnMajor)
     }
@@ -140,10 +221,19 @@ impl Map {
         Position::from_point(self.index_to_point2d(idx))
     }
 
-    pub fn clear_entities(&mut self) {
-        for content in self.entities.iter_mut() {
-            content.clear();
-        }
+    /// Revealed floor tiles next to at least one tile that was never seen
+    pub fn frontier(&self) -> Vec<usize> {
+        self.revealed
+            .indexed_iter()
+            .filter(|&(idx, &revealed)| revealed && self.tiles[idx] == TileType::Floor)
+            .filter(|&((x, y), _)| {
+                let (x, y) = (x as i32, y as i32);
+                [(x - 1, y), (x + 1, y), (x, y + 1), (x, y - 1)]
+                    .into_iter()
+                    .any(|(nx, ny)| self.revealed.get(nx, ny) == Some(&false))
+            })
+            .map(|((x, y), _)| self.to_idx(Point::new(x, y)))
+            .collect()
     }
 
     fn carve_room(&mut self, room: Rect) {
@@ -156,7 +246,49 @@ impl Map {
         }
     }
 
-    fn carve_horizontal_tunnel(&mut self, x1: i32, x2: i32, y: i32) {
+    fn light_room(&mut self, room: Rect) {
+        for y in room.y1 - 1..=room.y2 + 1 {
+            for x in room.x1 - 1..=room.x2 + 1 {
+                if let Some(lit) = self.lit.get_mut(x, y) {
+                    *lit = true
+                }
+            }
+        }
+    }
+
+    /// Give special rooms their layout and lighting
+    fn shape_room(&mut self, room: Rect, kind: RoomKind) {
+        match kind {
+            RoomKind::Plain => {}
+            RoomKind::Treasure | RoomKind::Shrine => self.light_room(room),
+            RoomKind::Lair => {
+                for y in room.y1..=room.y2 {
+                    for x in room.x1..=room.x2 {
+                        if let Some(lit) = self.lit.get_mut(x, y) {
+                            *lit = false
+                        }
+                    }
+                }
+            }
+            RoomKind::BossArena => {
+                self.light_room(room);
+                // single pillars off the center lines, so tunnels running through stay open
+                let center = room.center();
+                for y in (room.y1 + 2..room.y2 - 1).step_by(3) {
+                    for x in (room.x1 + 2..room.x2 - 1).step_by(3) {
+                        if x == center.x || y == center.y {
+                            continue;
+                        }
+                        if let Some(tile) = self.tiles.get_mut(x, y) {
+                            *tile = TileType::Wall
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn carve_horizontal_tunnel(&mut self, x1: i32, x2: i32, y: i32) {
         for x in x1.min(x2)..=x1.max(x2) {
             if let Some(tile) = self.tiles.get_mut(x, y) {
                 *tile = TileType::Floor
@@ -164,7 +296,7 @@ impl Map {
         }
     }
 
-    fn carve_vertical_tunnel(&mut self, x: i32, y1: i32, y2: i32) {
+    pub fn carve_vertical_tunnel(&mut self, x: i32, y1: i32, y2: i32) {
         for y in y1.min(y2)..=y1.max(y2) {
             if let Some(tile) = self.tiles.get_mut(x, y) {
                 *tile = TileType::Floor
@@ -0,0 +1,85 @@
+use bracket_lib::prelude::*;
+
+use crate::{
+    combat::CombatStats, position::Position, trap::Hidden, Name, RenderOrder, Renderable, State,
+};
+
+/// The last glimpse the player caught of an entity
+#[derive(Debug, Clone)]
+pub struct Remembered {
+    pub pos: Position,
+    pub glyph: FontCharType,
+    pub order: RenderOrder,
+    pub name: String,
+    pub creature: bool,
+    /// Turn on which the entity was last seen
+    pub turn: u32,
+}
+
+impl State {
+    /// Update the memory of the level with everything currently in view
+    pub fn remember_entities(&mut self) {
+        // whatever we remember on a tile we can see now is either still there or gone
+        let visible = &self.map.visible;
+        self.map
+            .remembered
+            .retain(|_, remembered| !visible[remembered.pos.into()]);
+
+        type Q<'w> = (
+            &'w Position,
+            &'w Renderable,
+            &'w Name,
+            Option<&'w CombatStats>,
+        );
+        for (e, (pos, render, name, stats)) in self.world.query::<Q>().without::<&Hidden>().iter() {
+            if e == self.player || !self.map.visible[pos.into()] {
+                continue;
+            }
+            self.map.remembered.insert(
+                e,
+                Remembered {
+                    pos: *pos,
+                    glyph: render.glyph,
+                    order: render.order,
+                    name: name.to_string(),
+                    creature: stats.is_some(),
+                    turn: self.turn,
+                },
+            );
+        }
+    }
+
+    /// Draw remembered entities dimmed on tiles that are out of sight
+    pub fn draw_remembered(&self, draw_batch: &mut DrawBatch) {
+        let mut remembered: Vec<&Remembered> = self
+            .map
+            .remembered
+            .values()
+            .filter(|r| self.map.revealed[r.pos.into()] && !self.map.visible[r.pos.into()])
+            .collect();
+        remembered.sort_by_key(|r| r.order);
+        for r in remembered {
+            draw_batch.set(
+                r.pos.into(),
+                ColorPair::new(RGBA::from_f32(0.5, 0.5, 0.5, 1.0), RGBA::named(BLACK)),
+                r.glyph,
+            );
+        }
+    }
+
+    /// Descriptions of what the player remembers on a tile
+    pub fn remembered_at(&self, pos: Position) -> Vec<String> {
+        self.map
+            .remembered
+            .values()
+            .filter(|r| r.pos == pos)
+            .map(|r| {
+                if r.creature {
+                    format!("{} (seen {} turns ago)", r.name, self.turn - r.turn)
+                } else {
+                    r.name.clone()
+                }
+            })
+            .collect()
+    }
+}
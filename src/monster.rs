@@ -1,35 +1,265 @@
+use std::collections::HashSet;
+
 use bracket_lib::prelude::*;
+use hecs::Entity;
+
+use crate::{
+    ability::{self, Abilities, Mana},
+    combat::{CombatStats, WantsToMelee},
+    companion::Companion,
+    event::Event,
+    map::Map,
+    movement::WantsToMove,
+    noise::Investigating,
+    position::Position,
+    status::Confused,
+    stealth::{self, Awareness, AwarenessLevel},
+    State, ViewShed,
+};
 
-use crate::{combat::WantsToMelee, position::Position, Name, State, ViewShed};
+/// Extra cost of a path across a trap the monsters know about
+const TRAP_COST: f32 = 10.0;
 
 #[derive(Debug)]
 pub struct Monster;
 
+/// Does nothing until woken by the player coming close, getting hurt or a loud noise
+#[derive(Debug)]
+pub struct Asleep;
+
+/// The map as the monsters path over it, where crossing a trap they know about costs extra
+pub struct Wary<'a> {
+    pub map: &'a Map,
+    pub traps: &'a HashSet<usize>,
+}
+
+impl BaseMap for Wary<'_> {
+    fn is_opaque(&self, idx: usize) -> bool {
+        self.map.is_opaque(idx)
+    }
+
+    fn get_pathing_distance(&self, idx1: usize, idx2: usize) -> f32 {
+        self.map.get_pathing_distance(idx1, idx2)
+    }
+
+    fn get_available_exits(&self, idx: usize) -> SmallVec<[(usize, f32); 10]> {
+        self.map
+            .get_available_exits(idx)
+            .into_iter()
+            .map(|(exit, cost)| {
+                let trap = if self.traps.contains(&exit) {
+                    TRAP_COST
+                } else {
+                    0.0
+                };
+                (exit, cost + trap)
+            })
+            .collect()
+    }
+}
+
+/// Anything that gets hurt wakes up and knows it is in a fight
+pub fn wake(gs: &mut State, event: &Event) {
+    let &Event::Damaged { victim, .. } = event else {
+        return;
+    };
+    _ = gs.world.remove_one::<Asleep>(victim);
+    if let Ok(awareness) = gs.world.query_one_mut::<&mut Awareness>(victim) {
+        *awareness = Awareness::alert();
+    }
+}
+
 pub fn apply_ai(gs: &mut State) {
     let player_pos = *gs.world.query_one_mut::<&Position>(gs.player).unwrap();
 
+    let mut woken = Vec::new();
+    type S<'w> = (&'w Position, &'w ViewShed);
+    for (e, (pos, fov)) in gs.world.query_mut::<S>().with::<(&Monster, &Asleep)>() {
+        if fov.visible_tiles.contains(&player_pos)
+            && DistanceAlg::Pythagoras.distance2d(pos.into(), player_pos.into()) < 3.0
+        {
+            woken.push(e);
+        }
+    }
+    for e in woken {
+        _ = gs.world.remove_one::<Asleep>(e);
+        if let Ok(awareness) = gs.world.query_one_mut::<&mut Awareness>(e) {
+            awareness.raise(AwarenessLevel::Suspicious);
+        }
+    }
+    stealth::update(gs);
+
+    let companions: Vec<(Entity, Position)> = gs
+        .world
+        .query_mut::<&Position>()
+        .with::<&Companion>()
+        .into_iter()
+        .map(|(e, pos)| (e, *pos))
+        .collect();
+
     let mut attackers = Vec::new();
-    type Q<'w> = (&'w mut Position, &'w Name, &'w mut ViewShed);
-    for (e, (pos, _, fov)) in gs.world.query_mut::<Q>().with::<&Monster>() {
-        if fov.visible_tiles.contains(&player_pos) {
-            let Some(exit) = DijkstraMap::find_lowest_exit(&gs.dm, gs.map.to_idx(*pos), &gs.map)
+    let mut moves = Vec::new();
+    let mut casters = Vec::new();
+    let mut done_investigating = Vec::new();
+    let wary = Wary {
+        map: &gs.map,
+        traps: &gs.known_traps,
+    };
+    type Q<'w> = (
+        &'w Position,
+        &'w ViewShed,
+        &'w Awareness,
+        Option<&'w Confused>,
+        Option<&'w Investigating>,
+        &'w CombatStats,
+        Option<&'w Abilities>,
+        Option<&'w Mana>,
+    );
+    for (e, (pos, fov, awareness, confused, investigating, stats, abilities, mana)) in gs
+        .world
+        .query_mut::<Q>()
+        .with::<&Monster>()
+        .without::<&Asleep>()
+    {
+        if confused.is_some() {
+            moves.push((e, *pos + random_direction(&mut gs.rng)));
+        } else if fov.visible_tiles.contains(&player_pos)
+            && awareness.level() == AwarenessLevel::Alert
+        {
+            if let Some(cast) =
+                abilities.and_then(|a| ability::choose(a, mana, stats, &gs.map, *pos, player_pos))
+            {
+                casters.push((e, cast));
+                continue;
+            }
+            // companions standing in the way get attacked
+            let here = Point::from(*pos);
+            let next_to = |p: Position| DistanceAlg::Manhattan.distance2d(here, p.into()) <= 1.0;
+            if next_to(player_pos) {
+                attackers.push((e, gs.player));
+                continue;
+            }
+            if let Some(&(companion, _)) = companions.iter().find(|&&(_, p)| next_to(p)) {
+                attackers.push((e, companion));
+                continue;
+            }
+            // the chase map already charges for known traps, only ever step downhill on it
+            let from = gs.map.to_idx(*pos);
+            let Some(exit) = gs
+                .map
+                .get_available_exits(from)
+                .into_iter()
+                .map(|(idx, _)| idx)
+                .filter(|&idx| gs.dm.map[idx] < gs.dm.map[from])
+                .min_by(|&a, &b| gs.dm.map[a].total_cmp(&gs.dm.map[b]))
             else {
                 continue;
             };
-
-            if exit == gs.map.to_idx(player_pos) {
-                attackers.push(e);
-            } else {
-                *pos = gs.map.to_pos(exit);
-                fov.dirty = true;
+            moves.push((e, gs.map.to_pos(exit)));
+        } else if let Some(&Investigating { target }) = investigating {
+            let path = a_star_search(gs.map.to_idx(*pos), gs.map.to_idx(target), &wary);
+            if path.success && path.steps.len() > 1 {
+                moves.push((e, gs.map.to_pos(path.steps[1])));
+            }
+            if !path.success || path.steps.len() <= 2 {
+                done_investigating.push(e);
             }
         }
     }
 
-    for a in attackers {
-        if let Err(err) = gs.world.insert_one(a, WantsToMelee { target: gs.player }) {
+    for (e, destination) in moves {
+        _ = gs.world.insert_one(e, WantsToMove { destination });
+    }
+
+    for (e, cast) in casters {
+        _ = gs.world.insert_one(e, cast);
+    }
+
+    for e in done_investigating {
+        _ = gs.world.remove_one::<Investigating>(e);
+    }
+
+    for (a, target) in attackers {
+        if let Err(err) = gs.world.insert_one(a, WantsToMelee { target }) {
             gs.msg_log
                 .push(format!("Error inserting Melee component: {err}"));
         }
     }
 }
+
+pub fn random_direction(rng: &mut RandomNumberGenerator) -> Point {
+    [
+        Point::new(-1, 0),
+        Point::new(1, 0),
+        Point::new(0, 1),
+        Point::new(0, -1),
+    ][rng.range::<usize>(0, 4)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        combat::Percentage, damage::DamageType, formula::Dice, movement, spawn, BlocksTile, Name,
+        Options,
+    };
+
+    #[test]
+    fn chase_goes_around_walls() {
+        let mut gs = State::new(1, Options::default());
+        gs.map = Map::from_text(
+            "name: test\n\
+             map:\n\
+             #######\n\
+             #.....#\n\
+             #@.#..#\n\
+             #.....#\n\
+             #######\n",
+        )
+        .unwrap();
+        let start = gs.map.start;
+        gs.player = spawn::player(&mut gs.world, &mut gs.rng, &gs.options, start);
+        let stats = CombatStats {
+            max_hp: 10,
+            hp: 10,
+            accuracy: Percentage::new(1.0),
+            evasion: Percentage::new(0.0),
+            armor: 0,
+            damage: Dice::new(1, 4, 0),
+            damage_type: DamageType::Slashing,
+        };
+        // it has spotted the player, who stands right behind the wall
+        let mut fov = ViewShed::new(8);
+        fov.visible_tiles.insert(start);
+        let goblin = gs.world.spawn((
+            Position::new(4, 2),
+            Name::new("Goblin"),
+            Monster,
+            Awareness::alert(),
+            fov,
+            stats,
+            BlocksTile,
+        ));
+        gs.index_entities();
+
+        let mut trail = Vec::new();
+        for _ in 0..3 {
+            gs.compute_dijkstra_map();
+            apply_ai(&mut gs);
+            movement::run(&mut gs);
+            trail.push(*gs.world.get::<&Position>(goblin).unwrap());
+        }
+
+        // around either end of the wall, then straight on towards the player
+        let y = trail[0].y;
+        assert!(y == 1 || y == 3, "walked into the wall: {trail:?}");
+        assert_eq!(
+            trail,
+            [
+                Position::new(4, y),
+                Position::new(3, y),
+                Position::new(2, y)
+            ]
+        );
+    }
+}
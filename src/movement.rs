@@ -0,0 +1,76 @@
+use std::collections::HashSet;
+
+use bracket_lib::prelude::*;
+use hecs::Entity;
+
+use crate::{event::Event, position::Position, trap, State, ViewShed};
+
+/// Asks for an entity to be moved to a neighbouring tile on the next movement pass
+#[derive(Debug, Clone, Copy)]
+pub struct WantsToMove {
+    pub destination: Position,
+}
+
+/// Carry out every movement asked for this turn.
+/// The player gets first pick of a contested tile, anybody finding their destination
+/// taken stays put, and two entities stepping onto each other's tile trade places.
+pub fn run(gs: &mut State) {
+    let mut intents: Vec<(Entity, Position, Position)> = gs
+        .world
+        .query_mut::<(&Position, &WantsToMove)>()
+        .into_iter()
+        .map(|(e, (pos, wants))| (e, *pos, wants.destination))
+        .collect();
+    for &(e, ..) in &intents {
+        _ = gs.world.remove_one::<WantsToMove>(e);
+    }
+    intents.sort_by_key(|&(e, ..)| e != gs.player);
+
+    let mut moved = HashSet::new();
+    let mut entered = Vec::new();
+    for &(e, from, to) in &intents {
+        if moved.contains(&e) || from == to || !is_open(gs, to) {
+            continue;
+        }
+        match gs.map.index.blocker_at(to) {
+            None => {
+                relocate(gs, e, to);
+                moved.insert(e);
+                entered.push((e, to));
+            }
+            Some(other) => {
+                let swapping = intents
+                    .iter()
+                    .any(|&(o, o_from, o_to)| o == other && o_from == to && o_to == from);
+                if swapping && !moved.contains(&other) {
+                    relocate(gs, e, to);
+                    relocate(gs, other, from);
+                    moved.extend([e, other]);
+                    entered.extend([(e, to), (other, from)]);
+                }
+            }
+        }
+    }
+
+    for (e, pos) in entered {
+        trap::entered(gs, e, pos);
+    }
+}
+
+/// Whether the tile is on the map and not a wall, whoever stands on it
+fn is_open(gs: &State, pos: Position) -> bool {
+    let p = Point::from(pos);
+    gs.map.in_bounds(p) && !gs.map.is_opaque(gs.map.to_idx(p))
+}
+
+/// Put an entity somewhere else right away, keeping the index and its field of view up to date
+pub fn relocate(gs: &mut State, e: Entity, dst: Position) {
+    if let Ok(pos) = gs.world.query_one_mut::<&mut Position>(e) {
+        *pos = dst;
+        gs.map.index.move_to(e, dst);
+        gs.events.publish(Event::Moved { entity: e, to: dst });
+    }
+    if let Ok(fov) = gs.world.query_one_mut::<&mut ViewShed>(e) {
+        fov.dirty = true;
+    }
+}
@@ -0,0 +1,119 @@
+use bracket_lib::prelude::*;
+
+use crate::{
+    map::Map,
+    monster::{Asleep, Monster},
+    position::Position,
+    stealth::{Awareness, AwarenessLevel},
+    Name, State,
+};
+
+/// How loud a sound still has to be when it reaches a sleeper to wake it up
+const WAKE_LOUDNESS: f32 = 5.0;
+
+pub const COMBAT_LOUDNESS: f32 = 8.0;
+pub const RUN_LOUDNESS: f32 = 4.0;
+pub const ALARM_LOUDNESS: f32 = 40.0;
+
+/// A sound made this turn, heard as far as its loudness carries
+#[derive(Debug, Clone, Copy)]
+pub struct Noise {
+    pub source: Position,
+    pub loudness: f32,
+}
+
+impl Noise {
+    pub fn new(source: Position, loudness: f32) -> Self {
+        Self { source, loudness }
+    }
+}
+
+/// Going to look at where a sound came from
+#[derive(Debug, Clone, Copy)]
+pub struct Investigating {
+    pub target: Position,
+}
+
+/// The map as sound travels through it: walls stop it, creatures do not
+struct Acoustics<'a>(&'a Map);
+
+impl BaseMap for Acoustics<'_> {
+    fn is_opaque(&self, idx: usize) -> bool {
+        self.0.is_opaque(idx)
+    }
+
+    fn get_available_exits(&self, idx: usize) -> SmallVec<[(usize, f32); 10]> {
+        let pt = self.0.index_to_point2d(idx);
+        [
+            Point::new(pt.x - 1, pt.y),
+            Point::new(pt.x + 1, pt.y),
+            Point::new(pt.x, pt.y + 1),
+            Point::new(pt.x, pt.y - 1),
+        ]
+        .into_iter()
+        .filter(|&p| self.0.in_bounds(p))
+        .map(|p| self.0.point2d_to_index(p))
+        .filter(|&idx| !self.0.is_opaque(idx))
+        .map(|idx| (idx, 1.0))
+        .collect()
+    }
+}
+
+/// Spread this turn's noises through the map and let monsters react to what they hear
+pub fn propagate(gs: &mut State) {
+    gs.sound.clear();
+    gs.sound.resize(gs.map.width * gs.map.height, 0.0);
+
+    let mut heard = Vec::new();
+    for noise in std::mem::take(&mut gs.noises) {
+        let dm = DijkstraMap::new(
+            gs.map.width,
+            gs.map.height,
+            &[gs.map.to_idx(noise.source)],
+            &Acoustics(&gs.map),
+            noise.loudness,
+        );
+        for (level, distance) in gs.sound.iter_mut().zip(&dm.map) {
+            *level = level.max(noise.loudness - distance);
+        }
+
+        for (e, pos) in gs.world.query_mut::<&Position>().with::<&Monster>() {
+            let remaining = noise.loudness - dm.map[gs.map.to_idx(*pos)];
+            if remaining > 0.0 {
+                heard.push((e, noise.source, remaining));
+            }
+        }
+    }
+
+    for (e, source, remaining) in heard {
+        if remaining >= WAKE_LOUDNESS && gs.world.remove_one::<Asleep>(e).is_ok() {
+            if let Ok((pos, name)) = gs.world.query_one_mut::<(&Position, &Name)>(e) {
+                if gs.map.visible[pos.into()] {
+                    gs.msg_log.push(format!("{name} wakes up."));
+                }
+            }
+        }
+        if matches!(gs.world.satisfies::<&Asleep>(e), Ok(false)) {
+            _ = gs.world.insert_one(e, Investigating { target: source });
+            if let Ok(awareness) = gs.world.query_one_mut::<&mut Awareness>(e) {
+                awareness.raise(AwarenessLevel::Suspicious);
+            }
+        }
+    }
+}
+
+impl State {
+    pub fn make_noise(&mut self, source: Position, loudness: f32) {
+        self.noises.push(Noise::new(source, loudness));
+    }
+
+    /// Show how loud the sounds of the last turn were on every tile they reached
+    pub fn draw_sound_overlay(&self, ctx: &mut BTerm) {
+        for (idx, &level) in self.sound.iter().enumerate() {
+            if level > 0.0 {
+                let p = self.map.to_pos(idx);
+                ctx.print_color(p.x, p.y, CYAN, BLACK, level.min(9.0) as i32);
+            }
+        }
+    }
+}
@@ -0,0 +1,547 @@
+use std::collections::{HashMap, VecDeque};
+
+use bracket_lib::prelude::*;
+use bracket_lib::terminal::console;
+use hecs::{Entity, World};
+
+use crate::{
+    boss::Boss,
+    companion::Recruitable,
+    hunger::Food,
+    light::LightSource,
+    map::{Map, TileType},
+    monster::Monster,
+    position::Position,
+    room::{Shrine, Treasure},
+    spawn,
+    trap::Trap,
+    Player,
+};
+
+/// Rooms stamped into generated levels where they fit
+const VAULTS: &[&str] = &[
+    include_str!("../resources/prefabs/crypt.txt"),
+    include_str!("../resources/prefabs/treasury.txt"),
+];
+
+/// Hand-built levels that replace generation entirely
+const LEVELS: &[&str] = &[include_str!("../resources/prefabs/town.txt")];
+
+/// Entities a legend can ask for, with the symbol standing for them when a level is written out
+const ENTITIES: &[(char, &str)] = &[
+    ('o', "monster"),
+    ('O', "boss"),
+    ('$', "gold"),
+    ('%', "ration"),
+    ('^', "trap"),
+    ('*', "brazier"),
+    ('_', "shrine"),
+    ('d', "dog"),
+];
+
+/// Attempts at finding a free spot for a vault
+const PLACEMENT_TRIES: i32 = 20;
+
+/// A hand-authored map section, read from a text file:
+///
+/// ```text
+/// # comment
+/// name: crypt
+/// rotate: true
+/// mirror: true
+/// lit: false
+/// legend: o=monster $=gold
+/// map:
+/// #####
+/// #o.$#
+/// ##+##
+/// ```
+///
+/// On the map `#` is a wall, `.` a floor, a space leaves the tile as it was, `+` is the entrance
+/// a vault gets connected through and `@` where the player starts on a fixed level.
+/// Any other character is a floor with the entity the legend maps it to.
+#[derive(Debug, Clone)]
+pub struct Prefab {
+    pub name: String,
+    rows: Vec<Vec<char>>,
+    legend: HashMap<char, String>,
+    /// Whether it may be turned by quarter turns when placed
+    rotate: bool,
+    /// Whether it may be flipped when placed
+    mirror: bool,
+    /// Whether its floor is lit
+    lit: bool,
+}
+
+impl Prefab {
+    pub fn parse(text: &str) -> Result<Prefab, String> {
+        let mut prefab = Prefab {
+            name: String::new(),
+            rows: Vec::new(),
+            legend: HashMap::new(),
+            rotate: false,
+            mirror: false,
+            lit: false,
+        };
+        let flag = |value: &str| match value {
+            "true" | "yes" => Ok(true),
+            "false" | "no" => Ok(false),
+            _ => Err(format!("expected true or false, got '{value}'")),
+        };
+
+        let mut lines = text.lines();
+        for line in lines.by_ref() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line == "map:" {
+                break;
+            }
+            let Some((key, value)) = line.split_once(':') else {
+                return Err(format!("expected 'key: value', got '{line}'"));
+            };
+            let value = value.trim();
+            match key.trim() {
+                "name" => prefab.name = value.to_string(),
+                "rotate" => prefab.rotate = flag(value)?,
+                "mirror" => prefab.mirror = flag(value)?,
+                "lit" => prefab.lit = flag(value)?,
+                "legend" => {
+                    for entry in value.split_whitespace() {
+                        let mut chars = entry.chars();
+                        let (Some(c), Some('=')) = (chars.next(), chars.next()) else {
+                            return Err(format!("bad legend entry '{entry}'"));
+                        };
+                        let entity = chars.as_str();
+                        if !ENTITIES.iter().any(|&(_, known)| known == entity) {
+                            return Err(format!("unknown entity '{entity}'"));
+                        }
+                        prefab.legend.insert(c, entity.to_string());
+                    }
+                }
+                key => return Err(format!("unknown key '{key}'")),
+            }
+        }
+
+        prefab.rows = lines.map(|line| line.chars().collect()).collect();
+        while prefab.rows.last().is_some_and(|row| row.is_empty()) {
+            prefab.rows.pop();
+        }
+        if prefab.rows.is_empty() {
+            return Err(format!("{}: no map", prefab.name));
+        }
+        let width = prefab.width();
+        for row in &mut prefab.rows {
+            row.resize(width, ' ');
+        }
+        for &c in prefab.rows.iter().flatten() {
+            if !matches!(c, '#' | '.' | ' ' | '+' | '@') && !prefab.legend.contains_key(&c) {
+                return Err(format!("{}: '{c}' is not in the legend", prefab.name));
+            }
+        }
+        Ok(prefab)
+    }
+
+    pub fn width(&self) -> usize {
+        self.rows.iter().map(|row| row.len()).max().unwrap_or(0)
+    }
+
+    pub fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Turned a quarter turn clockwise
+    fn rotated(&self) -> Prefab {
+        let rows = (0..self.width())
+            .map(|x| self.rows.iter().rev().map(|row| row[x]).collect())
+            .collect();
+        Prefab {
+            rows,
+            ..self.clone()
+        }
+    }
+
+    /// Flipped left to right
+    fn mirrored(&self) -> Prefab {
+        let rows = self
+            .rows
+            .iter()
+            .map(|row| row.iter().rev().copied().collect())
+            .collect();
+        Prefab {
+            rows,
+            ..self.clone()
+        }
+    }
+
+    /// A random orientation among those it allows
+    fn oriented(&self, rng: &mut RandomNumberGenerator) -> Prefab {
+        let mut prefab = self.clone();
+        if self.rotate {
+            for _ in 0..rng.range(0, 4) {
+                prefab = prefab.rotated();
+            }
+        }
+        if self.mirror && rng.rand() {
+            prefab = prefab.mirrored();
+        }
+        prefab
+    }
+
+    /// Every cell with its offset from the top left corner
+    fn cells(&self) -> impl Iterator<Item = (Point, char)> + '_ {
+        self.rows.iter().enumerate().flat_map(|(y, row)| {
+            row.iter()
+                .enumerate()
+                .map(move |(x, &c)| (Point::new(x, y), c))
+        })
+    }
+
+    fn find(&self, wanted: char) -> Option<Point> {
+        self.cells().find(|&(_, c)| c == wanted).map(|(p, _)| p)
+    }
+}
+
+fn parse_all(texts: &[&str]) -> Vec<Prefab> {
+    texts
+        .iter()
+        .filter_map(|text| match Prefab::parse(text) {
+            Ok(prefab) => Some(prefab),
+            Err(err) => {
+                console::log(format!("Error loading prefab: {err}"));
+                None
+            }
+        })
+        .collect()
+}
+
+/// A fixed level by name
+pub fn level(name: &str) -> Option<Prefab> {
+    parse_all(LEVELS)
+        .into_iter()
+        .find(|level| level.name == name)
+}
+
+impl Map {
+    /// A level made entirely from a prefab, the rest of the map is solid rock
+    pub fn from_prefab(prefab: &Prefab, width: usize, height: usize) -> Map {
+        let mut map = Map::empty(width, height);
+        map.stamp(prefab, Point::zero());
+        // without a marked start the player begins on the first plain floor tile
+        if let Some(start) = prefab.find('@').or_else(|| prefab.find('.')) {
+            map.start = Position::from_point(start);
+        }
+        if let Err(err) = map.validate() {
+            console::log(format!("Level {} is broken: {err}", prefab.name));
+        }
+        map
+    }
+
+    /// Read a level written by `to_text`, or any other prefab, sized to fit it exactly
+    #[cfg(test)]
+    pub fn from_text(text: &str) -> Result<Map, String> {
+        let prefab = Prefab::parse(text)?;
+        Ok(Map::from_prefab(&prefab, prefab.width(), prefab.height()))
+    }
+
+    /// Write the level out in the prefab format, with `@` on the start.
+    /// A tile holds a single symbol: when several entities share a tile the last one listed
+    /// is written, and `@` takes the place of anything on the start.
+    pub fn to_text(&self, name: &str, entities: &[(Position, String)]) -> String {
+        let mut rows: Vec<Vec<char>> = (0..self.height)
+            .map(|y| {
+                (0..self.width)
+                    .map(|x| match self.tiles[(x, y)] {
+                        TileType::Wall => '#',
+                        TileType::Floor => '.',
+                    })
+                    .collect()
+            })
+            .collect();
+        let mut legend = Vec::new();
+        for (pos, entity) in entities {
+            let Some(&(c, kind)) = ENTITIES.iter().find(|&&(_, kind)| kind == entity) else {
+                continue;
+            };
+            rows[usize::from(pos.y)][usize::from(pos.x)] = c;
+            if !legend.contains(&(c, kind)) {
+                legend.push((c, kind));
+            }
+        }
+        rows[usize::from(self.start.y)][usize::from(self.start.x)] = '@';
+
+        let mut text = format!("name: {name}\n");
+        if !legend.is_empty() {
+            let entries: Vec<String> = legend
+                .iter()
+                .map(|(c, kind)| format!("{c}={kind}"))
+                .collect();
+            text += &format!("legend: {}\n", entries.join(" "));
+        }
+        text += "map:\n";
+        for row in rows {
+            text.extend(row);
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Try to fit each vault somewhere in untouched rock, linked to the nearest room
+    pub fn place_vaults(&mut self, rng: &mut RandomNumberGenerator) {
+        for vault in parse_all(VAULTS) {
+            if rng.roll_dice(1, 2) == 1 {
+                let vault = vault.oriented(rng);
+                if !self.place_vault(&vault, rng) {
+                    console::log(format!("No room for the {} vault", vault.name));
+                }
+            }
+        }
+    }
+
+    fn place_vault(&mut self, vault: &Prefab, rng: &mut RandomNumberGenerator) -> bool {
+        let (w, h) = (vault.width() as i32, vault.height() as i32);
+        if w + 2 >= self.width as i32 || h + 2 >= self.height as i32 {
+            return false;
+        }
+        for _ in 0..PLACEMENT_TRIES {
+            let x = rng.range(1, self.width as i32 - w - 1);
+            let y = rng.range(1, self.height as i32 - h - 1);
+            // keep a margin of rock so it does not cut into rooms or tunnels
+            let untouched = (y - 1..=y + h).all(|ty| {
+                (x - 1..=x + w).all(|tx| self.tiles.get(tx, ty) == Some(&TileType::Wall))
+            });
+            if !untouched {
+                continue;
+            }
+
+            let origin = Point::new(x, y);
+            self.stamp(vault, origin);
+            self.vaults.push(Rect::with_size(x, y, w, h));
+            let entrance = vault.find('+').unwrap_or(Point::new(w / 2, h / 2));
+            // step out of the vault through the side closest to the entrance
+            let outward = [
+                (entrance.x, Point::new(-1, 0)),
+                (w - 1 - entrance.x, Point::new(1, 0)),
+                (entrance.y, Point::new(0, -1)),
+                (h - 1 - entrance.y, Point::new(0, 1)),
+            ]
+            .into_iter()
+            .min_by_key(|&(distance, _)| distance)
+            .map(|(distance, step)| step * (distance + 1))
+            .expect("A vault has four sides");
+            let outside = origin + entrance + outward;
+            let nearest = self
+                .rooms
+                .iter()
+                .map(|room| room.center())
+                .min_by(|&a, &b| {
+                    let distance = |p| DistanceAlg::Pythagoras.distance2d(outside, p);
+                    distance(a).total_cmp(&distance(b))
+                });
+            if let Some(room) = nearest {
+                self.carve_tunnel_around_vaults(outside, room);
+            }
+            return true;
+        }
+        false
+    }
+
+    /// Carve the shortest tunnel between two points that stays out of every vault, so their
+    /// entrances remain the only way in, and off the map's edge.
+    /// Nothing is carved if there is no such way.
+    fn carve_tunnel_around_vaults(&mut self, from: Point, to: Point) {
+        let (width, height) = (self.width as i32, self.height as i32);
+        let mut came_from = HashMap::from([(from, from)]);
+        let mut queue = VecDeque::from([from]);
+        while let Some(p) = queue.pop_front() {
+            if p == to {
+                break;
+            }
+            for step in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+                let next = p + Point::from_tuple(step);
+                let inside = next.x > 0 && next.y > 0 && next.x < width - 1 && next.y < height - 1;
+                let in_vault = self.vaults.iter().any(|vault| vault.point_in_rect(next));
+                if inside && !in_vault && !came_from.contains_key(&next) {
+                    came_from.insert(next, p);
+                    queue.push_back(next);
+                }
+            }
+        }
+        if !came_from.contains_key(&to) {
+            return;
+        }
+        let mut p = to;
+        loop {
+            if let Some(tile) = self.tiles.get_mut(p.x, p.y) {
+                *tile = TileType::Floor;
+            }
+            if p == from {
+                break;
+            }
+            p = came_from[&p];
+        }
+    }
+
+    /// Copy the tiles of a prefab onto the map and queue up its entities
+    fn stamp(&mut self, prefab: &Prefab, origin: Point) {
+        for (offset, c) in prefab.cells() {
+            let p = origin + offset;
+            let Some(tile) = self.tiles.get_mut(p.x, p.y) else {
+                continue;
+            };
+            *tile = match c {
+                ' ' => continue,
+                '#' => TileType::Wall,
+                _ => TileType::Floor,
+            };
+            if prefab.lit && *tile == TileType::Floor {
+                if let Some(lit) = self.lit.get_mut(p.x, p.y) {
+                    *lit = true;
+                }
+            }
+            if let Some(entity) = prefab.legend.get(&c) {
+                self.spawns.push((Position::from_point(p), entity.clone()));
+            }
+        }
+    }
+}
+
+/// Spawn the entities prefabs placed on the map
+pub fn spawn_entities(world: &mut World, rng: &mut RandomNumberGenerator, map: &Map) {
+    for (pos, entity) in &map.spawns {
+        if spawn::named(world, rng, entity, *pos).is_none() {
+            console::log(format!("Unknown prefab entity: {entity}"));
+        }
+    }
+}
+
+/// The prefab entity `e` would be written out as, if it is one
+fn entity_kind(world: &World, e: Entity) -> Option<&'static str> {
+    let entity = world.entity(e).ok()?;
+    let kind = if entity.has::<Boss>() {
+        "boss"
+    } else if entity.has::<Recruitable>() {
+        "dog"
+    } else if entity.has::<Monster>() {
+        "monster"
+    } else if entity.has::<Treasure>() {
+        "gold"
+    } else if entity.has::<Food>() {
+        "ration"
+    } else if entity.has::<Trap>() {
+        "trap"
+    } else if entity.has::<Shrine>() {
+        "shrine"
+    } else if entity.has::<LightSource>() && !entity.has::<Player>() {
+        "brazier"
+    } else {
+        return None;
+    };
+    Some(kind)
+}
+
+/// Every entity on the map that can be written out with it
+pub fn placed_entities(world: &World) -> Vec<(Position, String)> {
+    world
+        .query::<&Position>()
+        .iter()
+        .filter_map(|(e, pos)| Some((*pos, entity_kind(world, e)?.to_string())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(rows: &str) -> Map {
+        Map::from_text(&format!("name: test\nmap:\n{rows}")).unwrap()
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let generated = Map::new(60, 32, &mut RandomNumberGenerator::seeded(7));
+        let gold = (0..generated.width * generated.height)
+            .map(|idx| generated.to_pos(idx))
+            .find(|&pos| pos != generated.start && generated.tiles[pos.into()] == TileType::Floor)
+            .unwrap();
+        let entities = vec![(gold, "gold".to_string())];
+        let text = generated.to_text("round trip", &entities);
+
+        let loaded = Map::from_text(&text).unwrap();
+        assert_eq!(
+            (loaded.width, loaded.height),
+            (generated.width, generated.height)
+        );
+        assert_eq!(loaded.start, generated.start);
+        assert_eq!(loaded.spawns, entities);
+        assert_eq!(loaded.to_text("round trip", &entities), text);
+    }
+
+    #[test]
+    fn walls_block_the_view() {
+        let map = map("#######\n\
+                       #@.#..#\n\
+                       #..#..#\n\
+                       #.....#\n\
+                       #######\n");
+        let seen = field_of_view_set(map.start.into(), 8, &map);
+        assert!(seen.contains(&Point::new(2, 2)));
+        assert!(seen.contains(&Point::new(3, 1)));
+        assert!(!seen.contains(&Point::new(4, 1)));
+        assert!(!seen.contains(&Point::new(5, 1)));
+    }
+
+    #[test]
+    fn path_goes_around_walls() {
+        let map = map("#######\n\
+                       #@#...#\n\
+                       #.#.#.#\n\
+                       #...#.#\n\
+                       #######\n");
+        let path = a_star_search(map.to_idx(map.start), map.to_idx(Point::new(5, 1)), &map);
+        assert!(path.success);
+        let steps: Vec<(i32, i32)> = path
+            .steps
+            .iter()
+            .map(|&idx| map.index_to_point2d(idx).to_tuple())
+            .collect();
+        assert_eq!(
+            steps,
+            [
+                (1, 1),
+                (1, 2),
+                (1, 3),
+                (2, 3),
+                (3, 3),
+                (3, 2),
+                (3, 1),
+                (4, 1),
+                (5, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn tunnels_go_around_vaults() {
+        let mut map = map("#########\n\
+                           #@#####.#\n\
+                           #########\n\
+                           #########\n\
+                           #########\n");
+        let (vault, other) = (Rect::with_size(2, 0, 2, 3), Rect::with_size(5, 0, 2, 3));
+        map.vaults = vec![vault, other];
+        map.carve_tunnel_around_vaults(Point::new(1, 1), Point::new(7, 1));
+
+        for y in 0..3 {
+            for x in (2..4).chain(5..7) {
+                assert_eq!(
+                    map.tiles[(x, y)],
+                    TileType::Wall,
+                    "carved into a vault at {x},{y}"
+                );
+            }
+        }
+        assert!((1..8).all(|x| map.tiles[(x, 3)] == TileType::Floor));
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::HashSet;
+
+use bracket_lib::prelude::*;
+use hecs::{Entity, World};
+
+use crate::{
+    ability::Mana, combat::CombatStats, event::Event, position::Position, spawn, status::Confused,
+    Name, Options, Renderable, State,
+};
+
+/// Rolls for a free tile before giving up on placing something in a room
+const PLACEMENT_TRIES: i32 = 10;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RoomKind {
+    #[default]
+    Plain,
+    /// Valuables left on the floor, guarded by traps
+    Treasure,
+    /// A dark den packed with monsters
+    Lair,
+    /// A pillared hall where a boss waits
+    BossArena,
+    /// Holds a shrine that restores the player once
+    Shrine,
+}
+
+impl RoomKind {
+    /// Pick the kind of each room, the first one is where the player starts and stays plain
+    pub fn pick(rooms: &[Rect], rng: &mut RandomNumberGenerator) -> Vec<RoomKind> {
+        let mut kinds: Vec<RoomKind> = rooms
+            .iter()
+            .enumerate()
+            .map(|(i, _)| match rng.roll_dice(1, 12) {
+                _ if i == 0 => RoomKind::Plain,
+                1 => RoomKind::Treasure,
+                2 => RoomKind::Lair,
+                3 => RoomKind::Shrine,
+                _ => RoomKind::Plain,
+            })
+            .collect();
+
+        // the boss waits as far away from the start as possible
+        let start = rooms.first().map(|room| room.center()).unwrap_or_default();
+        let farthest = rooms.iter().enumerate().skip(1).max_by(|(_, a), (_, b)| {
+            let distance = |room: &Rect| DistanceAlg::Pythagoras.distance2d(start, room.center());
+            distance(a).total_cmp(&distance(b))
+        });
+        if let Some((i, _)) = farthest {
+            kinds[i] = RoomKind::BossArena;
+        }
+        kinds
+    }
+}
+
+/// A special room, announced the first time the player sees into it
+#[derive(Debug, Clone)]
+pub struct Encounter {
+    pub room: Rect,
+    pub kind: RoomKind,
+    /// Name of the boss waiting in the room
+    pub boss: Option<String>,
+    pub announced: bool,
+}
+
+/// Restores the player's health and mana, once
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Shrine {
+    pub used: bool,
+}
+
+/// Valuables the player can pick up
+#[derive(Debug, Clone, Copy)]
+pub struct Treasure {
+    pub value: i32,
+}
+
+/// Gold collected from treasures
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Purse {
+    pub gold: i32,
+}
+
+/// A random tile inside the room that nothing was put on yet, re-rolled a few times on collision
+pub fn free_tile(
+    rng: &mut RandomNumberGenerator,
+    room: Rect,
+    used: &mut HashSet<Position>,
+) -> Option<Position> {
+    (0..PLACEMENT_TRIES).find_map(|_| {
+        let x = rng.range(room.x1 + 1, room.x2);
+        let y = rng.range(room.y1 + 1, room.y2);
+        let pos = Position::new(x, y);
+        used.insert(pos).then_some(pos)
+    })
+}
+
+/// Spawn what belongs in a room of the given kind
+pub fn fill(
+    world: &mut World,
+    rng: &mut RandomNumberGenerator,
+    options: &Options,
+    room: Rect,
+    kind: RoomKind,
+) {
+    let center = Position::try_from(room.center()).unwrap();
+    let mut boss = None;
+    let mut used = HashSet::new();
+    match kind {
+        RoomKind::Plain => {
+            spawn::fill_room(world, rng, options, room, &mut used);
+            return;
+        }
+        RoomKind::Treasure => {
+            for _ in 0..rng.range(3, 6) {
+                if let Some(pos) = free_tile(rng, room, &mut used) {
+                    spawn::gold(world, rng, pos);
+                }
+            }
+            for _ in 0..rng.range(1, 3) {
+                if let Some(pos) = free_tile(rng, room, &mut used) {
+                    spawn::trap(world, rng, pos);
+                }
+            }
+        }
+        RoomKind::Lair => {
+            for _ in 0..rng.range(3, 6) {
+                if let Some(pos) = free_tile(rng, room, &mut used) {
+                    spawn::monster(world, rng, pos);
+                }
+            }
+        }
+        RoomKind::BossArena => {
+            let warlord = spawn::boss(world, center);
+            boss = world
+                .get::<&Name>(warlord)
+                .ok()
+                .map(|name| name.to_string());
+        }
+        RoomKind::Shrine => {
+            spawn::shrine(world, center);
+        }
+    }
+    world.spawn((Encounter {
+        room,
+        kind,
+        boss,
+        announced: false,
+    },));
+}
+
+/// Tell the player about special rooms as they come into view
+pub fn announce(gs: &mut State) {
+    for (_, encounter) in gs.world.query_mut::<&mut Encounter>() {
+        if encounter.announced {
+            continue;
+        }
+        let room = encounter.room;
+        let seen = (room.y1..=room.y2)
+            .flat_map(|y| (room.x1..=room.x2).map(move |x| (x, y)))
+            .any(|(x, y)| gs.map.visible.get(x, y) == Some(&true));
+        if !seen {
+            continue;
+        }
+        encounter.announced = true;
+        let msg = match (encounter.kind, &encounter.boss) {
+            (RoomKind::BossArena, Some(boss)) => format!("The {boss} awaits you in this arena!"),
+            (RoomKind::Treasure, _) => "You find a treasure vault!".into(),
+            (RoomKind::Lair, _) => "You stumble into a monster lair.".into(),
+            (RoomKind::Shrine, _) => "You discover a shrine.".into(),
+            _ => continue,
+        };
+        gs.msg_log.push(msg);
+    }
+}
+
+impl State {
+    /// Put the treasures lying on `pos` in the player's purse
+    pub fn collect_treasure(&mut self, pos: Position) -> bool {
+        let found: Vec<(Entity, i32, String)> = self
+            .world
+            .query::<(&Position, &Treasure, &Name)>()
+            .iter()
+            .filter(|(_, (p, _, _))| **p == pos)
+            .map(|(e, (_, treasure, name))| (e, treasure.value, name.to_string()))
+            .collect();
+        for (e, value, name) in &found {
+            if let Ok(purse) = self.world.query_one_mut::<&mut Purse>(self.player) {
+                purse.gold += value;
+            }
+            self.despawn(*e);
+            self.events.publish(Event::ItemPickedUp {
+                by: self.player,
+                name: name.clone(),
+                gold: *value,
+            });
+        }
+        !found.is_empty()
+    }
+
+    /// Pray at a shrine next to the player to be healed
+    pub fn pray(&mut self) -> bool {
+        let player_pos = *self.world.query_one_mut::<&Position>(self.player).unwrap();
+        let shrine = self
+            .world
+            .query_mut::<(&Position, &mut Shrine, &mut Renderable)>()
+            .into_iter()
+            .find(|(_, (pos, _, _))| {
+                DistanceAlg::Pythagoras.distance2d((*pos).into(), player_pos.into()) < 1.5
+            });
+        let Some((_, (_, shrine, render))) = shrine else {
+            self.msg_log.push("There is no shrine here.".into());
+            return false;
+        };
+        if shrine.used {
+            self.msg_log.push("The shrine is silent.".into());
+            return false;
+        }
+        shrine.used = true;
+        render.colors.fg = RGBA::named(GREY);
+
+        if let Ok(stats) = self.world.query_one_mut::<&mut CombatStats>(self.player) {
+            stats.hp = stats.max_hp;
+        }
+        if let Ok(mana) = self.world.query_one_mut::<&mut Mana>(self.player) {
+            mana.current = mana.max;
+        }
+        _ = self.world.remove_one::<Confused>(self.player);
+        self.msg_log
+            .push("You pray at the shrine and feel restored.".into());
+        true
+    }
+}
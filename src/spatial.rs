@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+
+use bracket_lib::prelude::{DistanceAlg, Point, Rect};
+use grid::{Grid, Order};
+use hecs::Entity;
+
+use crate::position::Position;
+
+/// Where every positioned entity stands, kept up to date as they spawn, move and despawn
+#[derive(Debug)]
+pub struct SpatialIndex {
+    tiles: Grid<Vec<Entity>>,
+    /// Position of each indexed entity and whether it blocks its tile
+    placed: HashMap<Entity, (Position, bool)>,
+}
+
+impl Default for SpatialIndex {
+    fn default() -> Self {
+        Self::new(0, 0)
+    }
+}
+
+impl SpatialIndex {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            tiles: Grid::new_with_order(width, height, Order::ColumnMajor),
+            placed: HashMap::new(),
+        }
+    }
+
+    /// Start tracking an entity, or update it if it is already tracked
+    pub fn insert(&mut self, e: Entity, pos: Position, blocks: bool) {
+        self.remove(e);
+        if let Some(content) = self.tiles.get_mut(pos.x, pos.y) {
+            content.push(e);
+            self.placed.insert(e, (pos, blocks));
+        }
+    }
+
+    /// Stop tracking an entity, returning where it was
+    pub fn remove(&mut self, e: Entity) -> Option<Position> {
+        let (pos, _) = self.placed.remove(&e)?;
+        self.tiles[pos.into()].retain(|&other| other != e);
+        Some(pos)
+    }
+
+    /// Move a tracked entity, untracked ones are left alone
+    pub fn move_to(&mut self, e: Entity, pos: Position) {
+        if let Some(&(_, blocks)) = self.placed.get(&e) {
+            self.insert(e, pos, blocks);
+        }
+    }
+
+    /// Forget everything and track the given entities instead
+    pub fn rebuild(&mut self, entities: impl IntoIterator<Item = (Entity, Position, bool)>) {
+        for content in self.tiles.iter_mut() {
+            content.clear();
+        }
+        self.placed.clear();
+        for (e, pos, blocks) in entities {
+            self.insert(e, pos, blocks);
+        }
+    }
+
+    /// Everything standing on a tile
+    pub fn at(&self, pos: Position) -> &[Entity] {
+        self.tiles
+            .get(pos.x, pos.y)
+            .map(|content| content.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// The entity blocking a tile, if any
+    pub fn blocker_at(&self, pos: Position) -> Option<Entity> {
+        self.at(pos)
+            .iter()
+            .copied()
+            .find(|e| self.placed.get(e).is_some_and(|&(_, blocks)| blocks))
+    }
+
+    pub fn is_blocked(&self, pos: Position) -> bool {
+        self.blocker_at(pos).is_some()
+    }
+
+    /// Everything inside a rectangle, edges included
+    pub fn in_rect(&self, rect: Rect) -> impl Iterator<Item = (Entity, Position)> + '_ {
+        let (x1, y1) = (rect.x1.max(0), rect.y1.max(0));
+        (y1..=rect.y2)
+            .flat_map(move |y| (x1..=rect.x2).map(move |x| (x, y)))
+            .filter_map(|(x, y)| {
+                self.tiles
+                    .get(x, y)
+                    .map(|content| (content, Position::new(x, y)))
+            })
+            .flat_map(|(content, pos)| content.iter().map(move |&e| (e, pos)))
+    }
+
+    /// Everything within `radius` tiles of `center`
+    pub fn in_radius(
+        &self,
+        center: Position,
+        radius: f32,
+    ) -> impl Iterator<Item = (Entity, Position)> + '_ {
+        let c = Point::from(center);
+        let r = radius.ceil() as i32;
+        self.in_rect(Rect::with_exact(c.x - r, c.y - r, c.x + r, c.y + r))
+            .filter(move |&(_, pos)| DistanceAlg::Pythagoras.distance2d(c, pos.into()) <= radius)
+    }
+}
@@ -1,67 +1,459 @@
+use std::collections::HashSet;
+
 use bracket_lib::prelude::*;
 use hecs::{Entity, World};
 
 use crate::{
-    combat::{CombatStats, Percentage},
-    monster::Monster,
+    ability::{Abilities, Ability, Mana},
+    boss::Boss,
+    combat::{CombatStats, Percentage, Regeneration},
+    companion::Recruitable,
+    corpse::Contents,
+    damage::{DamageType, Resistance, Resistances},
+    formula::Dice,
+    hunger::{Food, Hunger, MAX_FOOD},
+    light::LightSource,
+    monster::{Asleep, Monster},
     position::Position,
-    BlocksTile, Name, Player, Renderable, ViewShed,
+    room::{free_tile, Purse, Shrine, Treasure},
+    stealth::{Awareness, Stealth},
+    trap::{Hidden, Trap, TrapKind},
+    BlocksTile, Item, Name, Options, Player, RenderOrder, Renderable, ViewShed,
 };
 
-pub fn player(world: &mut World, _rng: &mut RandomNumberGenerator, position: Position) -> Entity {
-    world.spawn((
+pub fn player(
+    world: &mut World,
+    _rng: &mut RandomNumberGenerator,
+    options: &Options,
+    position: Position,
+) -> Entity {
+    let player = world.spawn((
         Player,
         position,
         Name::new("Player"),
         CombatStats {
             max_hp: 100,
             hp: 100,
-            accuracy: Percentage::new(0.7),
-            defense: Percentage::new(0.2),
-            power: 5,
+            accuracy: Percentage::new(0.8),
+            evasion: Percentage::new(0.15),
+            armor: 1,
+            damage: Dice::new(1, 6, 2),
+            damage_type: DamageType::Slashing,
         },
+        Regeneration::new(4, 1),
         Renderable {
             glyph: to_cp437('@'),
             colors: ColorPair {
                 fg: RGBA::named(YELLOW),
                 bg: RGBA::named(BLACK),
             },
+            order: RenderOrder::Player,
         },
         ViewShed::new(6),
-        // BlocksTile,
-    ))
+        Stealth(0.3),
+        Abilities(vec![
+            Ability::firebolt(),
+            Ability::frost_ray(),
+            Ability::confusion(),
+            Ability::fireball(),
+            Ability::heal(),
+            Ability::summon_wolf(),
+        ]),
+        Mana::new(30),
+        Purse::default(),
+        LightSource {
+            radius: 3,
+            color: RGB::from_f32(1.0, 0.85, 0.6),
+        },
+        BlocksTile,
+    ));
+    if options.hunger {
+        _ = world.insert_one(player, Hunger { food: MAX_FOOD });
+    }
+    player
+}
+
+/// The kinds of monster roaming the dungeon
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MonsterKind {
+    Orc,
+    GoblinShaman,
+    Goblin,
 }
 
+impl MonsterKind {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "orc" => Some(MonsterKind::Orc),
+            "shaman" => Some(MonsterKind::GoblinShaman),
+            "goblin" => Some(MonsterKind::Goblin),
+            _ => None,
+        }
+    }
+}
+
+/// A monster of a random kind
 pub fn monster(world: &mut World, rng: &mut RandomNumberGenerator, position: Position) -> Entity {
-    let (glyph, name) = match rng.roll_dice(1, 3) {
-        1 => (to_cp437('o'), Name::new("Orc")),
-        _ => (to_cp437('g'), Name::new("Goblin")),
+    let kind = match rng.roll_dice(1, 6) {
+        1 | 2 => MonsterKind::Orc,
+        3 => MonsterKind::GoblinShaman,
+        _ => MonsterKind::Goblin,
     };
-    world.spawn((
+    monster_of_kind(world, rng, position, kind)
+}
+
+fn monster_of_kind(
+    world: &mut World,
+    rng: &mut RandomNumberGenerator,
+    position: Position,
+    kind: MonsterKind,
+) -> Entity {
+    let goblin_resistances = || {
+        Resistances(vec![
+            (DamageType::Poison, Resistance::Immune),
+            (DamageType::Fire, Resistance::Vulnerable),
+        ])
+    };
+    let (glyph, name, damage, damage_type, resistances, torch, caster) = match kind {
+        MonsterKind::Orc => (
+            to_cp437('o'),
+            Name::new("Orc"),
+            Dice::new(1, 8, 0),
+            DamageType::Slashing,
+            Resistances(vec![(DamageType::Blunt, Resistance::Resistant)]),
+            true,
+            false,
+        ),
+        MonsterKind::GoblinShaman => (
+            to_cp437('g'),
+            Name::new("Goblin shaman"),
+            Dice::new(1, 4, 0),
+            DamageType::Blunt,
+            goblin_resistances(),
+            false,
+            true,
+        ),
+        MonsterKind::Goblin => (
+            to_cp437('g'),
+            Name::new("Goblin"),
+            Dice::new(1, 6, 0),
+            DamageType::Piercing,
+            goblin_resistances(),
+            false,
+            false,
+        ),
+    };
+    let monster = world.spawn((
         Monster,
         position,
         name,
         CombatStats {
             max_hp: 16,
             hp: 16,
-            accuracy: Percentage::new(0.5),
-            defense: Percentage::new(0.1),
-            power: 4,
+            accuracy: Percentage::new(0.65),
+            evasion: Percentage::new(0.1),
+            armor: 0,
+            damage,
+            damage_type,
         },
+        resistances,
+        Regeneration::new(10, 1),
         Renderable {
             glyph,
             colors: ColorPair {
-                fg: RGBA::named(RED),
+                fg: RGBA::named(if caster { MAGENTA } else { RED }),
+                bg: RGBA::named(BLACK),
+            },
+            order: RenderOrder::Creature,
+        },
+        ViewShed::new(6),
+        Awareness::default(),
+        BlocksTile,
+    ));
+    if rng.roll_dice(1, 3) == 1 {
+        _ = world.insert_one(monster, Asleep);
+    }
+    if caster {
+        _ = world.insert(
+            monster,
+            (
+                Abilities(vec![Ability::firebolt(), Ability::heal()]),
+                Mana::new(15),
+            ),
+        );
+    }
+    if torch {
+        _ = world.insert_one(
+            monster,
+            LightSource {
+                radius: 2,
+                color: RGB::named(ORANGE),
+            },
+        );
+    }
+    monster
+}
+
+/// A creature fighting on whichever side it belongs to, without the monster AI
+fn beast(
+    world: &mut World,
+    position: Position,
+    name: &str,
+    glyph: char,
+    color: (u8, u8, u8),
+    max_hp: i32,
+) -> Entity {
+    world.spawn((
+        position,
+        Name::new(name),
+        CombatStats {
+            max_hp,
+            hp: max_hp,
+            accuracy: Percentage::new(0.7),
+            evasion: Percentage::new(0.2),
+            armor: 0,
+            damage: Dice::new(1, 6, 0),
+            damage_type: DamageType::Piercing,
+        },
+        Regeneration::new(6, 1),
+        Renderable {
+            glyph: to_cp437(glyph),
+            colors: ColorPair {
+                fg: RGBA::named(color),
                 bg: RGBA::named(BLACK),
             },
+            order: RenderOrder::Creature,
         },
         ViewShed::new(6),
         BlocksTile,
     ))
 }
 
-pub fn fill_room(world: &mut World, rng: &mut RandomNumberGenerator, room: Rect) {
-    let x = rng.range(room.x1 + 1, room.x2);
-    let y = rng.range(room.y1 + 1, room.y2);
-    monster(world, rng, Position::new(x, y));
+pub fn stray_dog(world: &mut World, position: Position) -> Entity {
+    let dog = beast(world, position, "Stray dog", 'd', (160, 110, 60), 20);
+    _ = world.insert_one(dog, Recruitable);
+    dog
+}
+
+pub fn wolf(world: &mut World, position: Position) -> Entity {
+    beast(world, position, "Wolf", 'w', LIGHT_BLUE, 24)
+}
+
+/// The Orc warlord, carrying its crown as a reward for whoever beats it
+pub fn boss(world: &mut World, position: Position) -> Entity {
+    let crown = world.spawn((
+        Item,
+        Name::new("Warlord's crown"),
+        Treasure { value: 500 },
+        Renderable {
+            glyph: to_cp437('$'),
+            colors: ColorPair {
+                fg: RGBA::named(GOLD),
+                bg: RGBA::named(BLACK),
+            },
+            order: RenderOrder::Item,
+        },
+    ));
+    world.spawn((
+        Monster,
+        Boss::default(),
+        position,
+        Name::new("Orc warlord"),
+        CombatStats {
+            max_hp: 60,
+            hp: 60,
+            accuracy: Percentage::new(0.75),
+            evasion: Percentage::new(0.1),
+            armor: 2,
+            damage: Dice::new(1, 10, 2),
+            damage_type: DamageType::Slashing,
+        },
+        Resistances(vec![(DamageType::Blunt, Resistance::Resistant)]),
+        Regeneration::new(20, 1),
+        Abilities::default(),
+        Mana::new(30),
+        Contents(vec![crown]),
+        Renderable {
+            glyph: to_cp437('O'),
+            colors: ColorPair {
+                fg: RGBA::named(ORANGE),
+                bg: RGBA::named(BLACK),
+            },
+            order: RenderOrder::Creature,
+        },
+        ViewShed::new(8),
+        Awareness::default(),
+        LightSource {
+            radius: 3,
+            color: RGB::named(ORANGE),
+        },
+        BlocksTile,
+    ))
+}
+
+pub fn gold(world: &mut World, rng: &mut RandomNumberGenerator, position: Position) -> Entity {
+    world.spawn((
+        Item,
+        position,
+        Name::new("Gold coins"),
+        Treasure {
+            value: rng.range(10, 50),
+        },
+        Renderable {
+            glyph: to_cp437('$'),
+            colors: ColorPair {
+                fg: RGBA::named(GOLD),
+                bg: RGBA::named(BLACK),
+            },
+            order: RenderOrder::Item,
+        },
+    ))
+}
+
+pub fn shrine(world: &mut World, position: Position) -> Entity {
+    world.spawn((
+        Shrine::default(),
+        position,
+        Name::new("Shrine"),
+        LightSource {
+            radius: 4,
+            color: RGB::from_f32(0.7, 0.8, 1.0),
+        },
+        Renderable {
+            glyph: to_cp437('_'),
+            colors: ColorPair {
+                fg: RGBA::named(LIGHT_BLUE),
+                bg: RGBA::named(BLACK),
+            },
+            order: RenderOrder::Item,
+        },
+        BlocksTile,
+    ))
+}
+
+pub fn ration(world: &mut World, position: Position) -> Entity {
+    world.spawn((
+        Item,
+        position,
+        Name::new("Ration"),
+        Food {
+            nutrition: MAX_FOOD,
+        },
+        Renderable {
+            glyph: to_cp437('%'),
+            colors: ColorPair {
+                fg: RGBA::named(GREEN),
+                bg: RGBA::named(BLACK),
+            },
+            order: RenderOrder::Item,
+        },
+    ))
+}
+
+pub fn trap(world: &mut World, rng: &mut RandomNumberGenerator, position: Position) -> Entity {
+    let kind = match rng.roll_dice(1, 6) {
+        1 => TrapKind::Teleport,
+        2 => TrapKind::Alarm,
+        3 => TrapKind::ConfusionGas { turns: 5 },
+        _ => TrapKind::Spikes { damage: 6 },
+    };
+    world.spawn((
+        Trap::new(kind),
+        Hidden,
+        position,
+        Name::new(kind.name()),
+        Renderable {
+            glyph: to_cp437('^'),
+            colors: ColorPair {
+                fg: kind.color(),
+                bg: RGBA::named(BLACK),
+            },
+            order: RenderOrder::Decal,
+        },
+    ))
+}
+
+pub fn brazier(world: &mut World, position: Position) -> Entity {
+    world.spawn((
+        position,
+        Name::new("Brazier"),
+        LightSource {
+            radius: 5,
+            color: RGB::from_f32(1.0, 0.6, 0.2),
+        },
+        Renderable {
+            glyph: to_cp437('*'),
+            colors: ColorPair {
+                fg: RGBA::named(ORANGE),
+                bg: RGBA::named(BLACK),
+            },
+            order: RenderOrder::Item,
+        },
+        BlocksTile,
+    ))
+}
+
+/// Whatever a prefab legend or a wizard command calls `name`, if it is anything
+pub fn named(
+    world: &mut World,
+    rng: &mut RandomNumberGenerator,
+    name: &str,
+    position: Position,
+) -> Option<Entity> {
+    let e = match name {
+        "monster" => monster(world, rng, position),
+        "boss" => boss(world, position),
+        "gold" => gold(world, rng, position),
+        "ration" => ration(world, position),
+        "trap" => trap(world, rng, position),
+        "brazier" => brazier(world, position),
+        "shrine" => shrine(world, position),
+        "dog" => stray_dog(world, position),
+        "wolf" => wolf(world, position),
+        _ => monster_of_kind(world, rng, position, MonsterKind::parse(name)?),
+    };
+    Some(e)
+}
+
+/// Spawn the monster and odds and ends of a plain room, each on a tile not in `used` yet,
+/// skipping whatever finds no room
+pub fn fill_room(
+    world: &mut World,
+    rng: &mut RandomNumberGenerator,
+    options: &Options,
+    room: Rect,
+    used: &mut HashSet<Position>,
+) {
+    if let Some(pos) = free_tile(rng, room, used) {
+        let monster = monster(world, rng, pos);
+        if options.hunger && rng.roll_dice(1, 4) == 1 {
+            let food = ration(world, pos);
+            _ = world.remove_one::<Position>(food);
+            _ = world.insert_one(monster, Contents(vec![food]));
+        }
+    }
+
+    if options.hunger && rng.roll_dice(1, 4) == 1 {
+        if let Some(pos) = free_tile(rng, room, used) {
+            ration(world, pos);
+        }
+    }
+
+    if rng.roll_dice(1, 3) == 1 {
+        if let Some(pos) = free_tile(rng, room, used) {
+            trap(world, rng, pos);
+        }
+    }
+
+    if rng.roll_dice(1, 8) == 1 {
+        if let Some(pos) = free_tile(rng, room, used) {
+            stray_dog(world, pos);
+        }
+    }
+
+    if rng.roll_dice(1, 4) == 1 {
+        if let Some(pos) = free_tile(rng, room, used) {
+            brazier(world, pos);
+        }
+    }
 }
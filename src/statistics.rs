@@ -0,0 +1,352 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use bracket_lib::prelude::*;
+use bracket_lib::terminal::console;
+
+use crate::{event::Event, Name, State, CONSOLE_HEIGHT, CONSOLE_WIDTH};
+
+/// Where past runs are kept, one per line
+const HISTORY_FILE: &str = "run_history.tsv";
+/// Runs listed on the history screen
+const HISTORY_ROWS: usize = 20;
+
+/// How the current run went
+#[derive(Debug, Clone, Default)]
+pub struct Statistics {
+    pub seed: u64,
+    pub turns: u32,
+    /// Monsters slain, by name
+    pub kills: BTreeMap<String, u32>,
+    pub damage_dealt: i32,
+    pub damage_taken: i32,
+    /// Melee attacks by the player that connected or missed
+    pub hits: u32,
+    pub misses: u32,
+    /// Number of levels entered
+    pub levels: u32,
+    pub items_used: u32,
+    pub gold: i32,
+    pub cause_of_death: Option<String>,
+    /// Whatever last hurt the player, the cause of death if it turns out to be fatal
+    last_hurt_by: Option<String>,
+}
+
+impl Statistics {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            ..Default::default()
+        }
+    }
+
+    pub fn total_kills(&self) -> u32 {
+        self.kills.values().sum()
+    }
+
+    pub fn score(&self) -> i32 {
+        self.gold + 25 * self.total_kills() as i32 + 100 * self.levels as i32
+    }
+
+    /// One tab separated line of the history file, prefixed with the date
+    fn to_line(&self, date: u64) -> String {
+        let kills: Vec<String> = self
+            .kills
+            .iter()
+            .map(|(name, count)| format!("{name}:{count}"))
+            .collect();
+        [
+            date.to_string(),
+            format!("{:016x}", self.seed),
+            self.turns.to_string(),
+            kills.join(","),
+            self.damage_dealt.to_string(),
+            self.damage_taken.to_string(),
+            self.hits.to_string(),
+            self.misses.to_string(),
+            self.levels.to_string(),
+            self.items_used.to_string(),
+            self.gold.to_string(),
+            self.cause_of_death.clone().unwrap_or_default(),
+        ]
+        .join("\t")
+    }
+
+    fn from_line(line: &str) -> Option<Run> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let &[date, seed, turns, kills, dealt, taken, hits, misses, levels, items, gold, cause] =
+            fields.as_slice()
+        else {
+            return None;
+        };
+        let mut stats = Statistics::new(u64::from_str_radix(seed, 16).ok()?);
+        stats.turns = turns.parse().ok()?;
+        for entry in kills.split(',').filter(|entry| !entry.is_empty()) {
+            let (name, count) = entry.rsplit_once(':')?;
+            stats.kills.insert(name.to_string(), count.parse().ok()?);
+        }
+        stats.damage_dealt = dealt.parse().ok()?;
+        stats.damage_taken = taken.parse().ok()?;
+        stats.hits = hits.parse().ok()?;
+        stats.misses = misses.parse().ok()?;
+        stats.levels = levels.parse().ok()?;
+        stats.items_used = items.parse().ok()?;
+        stats.gold = gold.parse().ok()?;
+        stats.cause_of_death = Some(cause.to_string()).filter(|cause| !cause.is_empty());
+        Some(Run {
+            date: date.parse().ok()?,
+            stats,
+        })
+    }
+}
+
+impl fmt::Display for Statistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(cause) = &self.cause_of_death {
+            writeln!(f, "Killed by {cause} on turn {}", self.turns)?;
+        }
+        writeln!(f, "Score: {}   Seed: {:016x}", self.score(), self.seed)?;
+        writeln!(f, "Levels entered: {}   Gold: {}", self.levels, self.gold)?;
+        writeln!(
+            f,
+            "Damage dealt: {}   taken: {}",
+            self.damage_dealt, self.damage_taken
+        )?;
+        writeln!(f, "Hits: {}   Misses: {}", self.hits, self.misses)?;
+        writeln!(f, "Items used: {}", self.items_used)?;
+        write!(f, "Monsters slain: {}", self.total_kills())?;
+        for (name, count) in &self.kills {
+            write!(f, "\n  {name}: {count}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A finished run in the history file
+#[derive(Debug, Clone)]
+pub struct Run {
+    /// Seconds since the Unix epoch
+    pub date: u64,
+    pub stats: Statistics,
+}
+
+/// Order of the runs on the history screen
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortBy {
+    #[default]
+    Score,
+    Date,
+    Turns,
+    Kills,
+}
+
+impl SortBy {
+    pub fn next(self) -> Self {
+        match self {
+            SortBy::Score => SortBy::Date,
+            SortBy::Date => SortBy::Turns,
+            SortBy::Turns => SortBy::Kills,
+            SortBy::Kills => SortBy::Score,
+        }
+    }
+
+    fn sort(self, runs: &mut [Run]) {
+        match self {
+            SortBy::Score => runs.sort_by_key(|run| std::cmp::Reverse(run.stats.score())),
+            SortBy::Date => runs.sort_by_key(|run| std::cmp::Reverse(run.date)),
+            SortBy::Turns => runs.sort_by_key(|run| std::cmp::Reverse(run.stats.turns)),
+            SortBy::Kills => runs.sort_by_key(|run| std::cmp::Reverse(run.stats.total_kills())),
+        }
+    }
+}
+
+impl fmt::Display for SortBy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SortBy::Score => "score".fmt(f),
+            SortBy::Date => "date".fmt(f),
+            SortBy::Turns => "turns".fmt(f),
+            SortBy::Kills => "kills".fmt(f),
+        }
+    }
+}
+
+/// Keep count of what happens to and around the player
+pub fn record(gs: &mut State, event: &Event) {
+    let player = gs.player;
+    let stats = &mut gs.stats;
+    match event {
+        Event::Attacked { attacker, .. } if attacker.entity == player => stats.hits += 1,
+        Event::Missed { attacker, .. } if attacker.entity == player => stats.misses += 1,
+        Event::Damaged {
+            victim,
+            source,
+            amount,
+            kind,
+            ..
+        } => {
+            if *source == Some(player) {
+                stats.damage_dealt += amount;
+            }
+            if *victim == player {
+                stats.damage_taken += amount;
+                stats.last_hurt_by = match source {
+                    Some(source) => gs
+                        .world
+                        .get::<&Name>(*source)
+                        .ok()
+                        .map(|name| name.to_string()),
+                    None if kind.is_none() => Some("starvation".into()),
+                    None => None,
+                };
+            }
+        }
+        Event::Died { entity, .. } if *entity == player => {
+            stats.cause_of_death = Some(
+                stats
+                    .last_hurt_by
+                    .clone()
+                    .unwrap_or_else(|| "something".into()),
+            );
+        }
+        Event::Died {
+            name,
+            monster: true,
+            ..
+        } => *stats.kills.entry(name.clone()).or_default() += 1,
+        Event::ItemPickedUp { by, gold, .. } if *by == player => stats.gold += gold,
+        Event::ItemUsed { by, .. } if *by == player => stats.items_used += 1,
+        Event::LevelEntered { .. } => stats.levels += 1,
+        _ => {}
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn save_run(stats: &Statistics) {
+    use std::io::Write;
+
+    let line = stats.to_line(now());
+    let saved = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(HISTORY_FILE)
+        .and_then(|mut file| writeln!(file, "{line}"));
+    if let Err(err) = saved {
+        console::log(format!("Error saving the run to {HISTORY_FILE}: {err}"));
+    }
+}
+
+/// There is no clock nor file system in the browser, runs are not kept there
+#[cfg(target_arch = "wasm32")]
+fn save_run(_stats: &Statistics) {}
+
+/// Every run in the history file, unreadable lines are skipped
+fn load_history() -> Vec<Run> {
+    let Ok(text) = std::fs::read_to_string(HISTORY_FILE) else {
+        return Vec::new();
+    };
+    text.lines()
+        .filter_map(|line| {
+            let run = Statistics::from_line(line);
+            if run.is_none() {
+                console::log(format!("Skipping bad line in {HISTORY_FILE}: {line}"));
+            }
+            run
+        })
+        .collect()
+}
+
+/// A date as year-month-day, from days since the Unix epoch
+fn format_date(secs: u64) -> String {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = (secs / 86_400) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+impl State {
+    /// Wrap up the run once the player is dead and add it to the history,
+    /// unless wizard mode made it meaningless
+    pub fn finish_run(&mut self) {
+        self.stats.turns = self.turn;
+        if self.wizard.is_none() {
+            save_run(&self.stats);
+        }
+    }
+
+    pub fn draw_game_over(&self, ctx: &mut BTerm) {
+        ctx.set_active_console(1);
+        ctx.cls();
+        let lines: Vec<String> = self.stats.to_string().lines().map(String::from).collect();
+        let width = 50;
+        let height = lines.len() as i32 + 5;
+        let (x, y) = ((CONSOLE_WIDTH - width) / 2, 4);
+        ctx.draw_box(x, y, width, height, WHITE, BLACK);
+        ctx.print_color_centered(y + 1, RED, BLACK, "You have died");
+        for (row, line) in (y + 3..).zip(&lines) {
+            ctx.print(x + 2, row, line);
+        }
+        ctx.print_color_centered(y + height - 1, GREY, BLACK, "H: past runs   Escape: quit");
+    }
+
+    /// Read the past runs for the history screen
+    pub fn load_runs(&mut self, sort: SortBy) {
+        self.runs = load_history();
+        sort.sort(&mut self.runs);
+    }
+
+    pub fn draw_history(&self, ctx: &mut BTerm, sort: SortBy) {
+        ctx.set_active_console(1);
+        ctx.cls();
+        ctx.draw_box(0, 0, CONSOLE_WIDTH - 1, CONSOLE_HEIGHT - 1, WHITE, BLACK);
+        ctx.print_color_centered(1, YELLOW, BLACK, format!("Past runs, by {sort}"));
+        ctx.print_color(
+            2,
+            3,
+            GREY,
+            BLACK,
+            format!(
+                "{:<10}  {:<16}  {:>6}  {:>6}  {:>5}  {}",
+                "Date", "Seed", "Score", "Turns", "Kills", "Killed by"
+            ),
+        );
+
+        if self.runs.is_empty() {
+            ctx.print(2, 5, "No runs yet.");
+        }
+        for (y, run) in (5..).zip(self.runs.iter().take(HISTORY_ROWS)) {
+            let stats = &run.stats;
+            let line = format!(
+                "{:<10}  {:016x}  {:>6}  {:>6}  {:>5}  {}",
+                format_date(run.date),
+                stats.seed,
+                stats.score(),
+                stats.turns,
+                stats.total_kills(),
+                stats.cause_of_death.as_deref().unwrap_or("-"),
+            );
+            ctx.print(2, y, line);
+        }
+        ctx.print_color_centered(
+            CONSOLE_HEIGHT - 2,
+            GREY,
+            BLACK,
+            "Tab: change order   Escape: back",
+        );
+    }
+}
@@ -0,0 +1,23 @@
+use crate::State;
+
+/// Moves go in random directions until it wears off
+#[derive(Debug, Clone, Copy)]
+pub struct Confused {
+    pub turns: i32,
+}
+
+pub fn run(gs: &mut State) {
+    let mut recovered = Vec::new();
+    for (e, confused) in gs.world.query_mut::<&mut Confused>() {
+        confused.turns -= 1;
+        if confused.turns <= 0 {
+            recovered.push(e);
+        }
+    }
+    for e in recovered {
+        _ = gs.world.remove_one::<Confused>(e);
+        if e == gs.player {
+            gs.msg_log.push("You are no longer confused.".into());
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use bracket_lib::prelude::*;
+
+use crate::{monster::Asleep, noise::Investigating, position::Position, State, ViewShed};
+
+const SUSPICIOUS: f32 = 1.0;
+const ALERT: f32 = 3.0;
+const MAX_AWARENESS: f32 = 4.0;
+/// Awareness lost each turn the player is out of sight
+const DECAY: f32 = 0.25;
+/// Even in complete darkness a monster looking right at you may notice something
+const MIN_LIGHT: f32 = 0.2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AwarenessLevel {
+    Unaware,
+    Suspicious,
+    Alert,
+}
+
+/// How sure a monster is that the player is around, builds up while it can see them
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Awareness(pub f32);
+
+impl Awareness {
+    pub fn level(&self) -> AwarenessLevel {
+        match self.0 {
+            a if a >= ALERT => AwarenessLevel::Alert,
+            a if a >= SUSPICIOUS => AwarenessLevel::Suspicious,
+            _ => AwarenessLevel::Unaware,
+        }
+    }
+
+    pub fn alert() -> Self {
+        Self(MAX_AWARENESS)
+    }
+
+    /// Raise the awareness to at least `level`
+    pub fn raise(&mut self, level: AwarenessLevel) {
+        let minimum = match level {
+            AwarenessLevel::Unaware => 0.0,
+            AwarenessLevel::Suspicious => SUSPICIOUS,
+            AwarenessLevel::Alert => ALERT,
+        };
+        self.0 = self.0.max(minimum);
+    }
+}
+
+/// How hard it is to notice the player, from 0 (not at all) to 1 (invisible)
+#[derive(Debug, Clone, Copy)]
+pub struct Stealth(pub f32);
+
+/// Let monsters that can see the player grow more aware of them, and the rest forget
+pub fn update(gs: &mut State) {
+    let player_pos = *gs.world.query_one_mut::<&Position>(gs.player).unwrap();
+    let stealth = gs
+        .world
+        .get::<&Stealth>(gs.player)
+        .map(|s| s.0)
+        .unwrap_or_default();
+    let light = gs.map.light[player_pos.into()];
+    let brightness = light.r.max(light.g).max(light.b).max(MIN_LIGHT);
+
+    let mut suspicious = Vec::new();
+    type Q<'w> = (&'w Position, &'w ViewShed, &'w mut Awareness);
+    for (e, (pos, fov, awareness)) in gs.world.query_mut::<Q>().without::<&Asleep>() {
+        if fov.visible_tiles.contains(&player_pos) {
+            let distance = DistanceAlg::Pythagoras.distance2d(pos.into(), player_pos.into());
+            let gain = (1.0 - stealth) * brightness / (1.0 + distance / 2.0);
+            awareness.0 = (awareness.0 + gain).min(MAX_AWARENESS);
+            if awareness.level() == AwarenessLevel::Suspicious {
+                suspicious.push(e);
+            }
+        } else {
+            awareness.0 = (awareness.0 - DECAY).max(0.0);
+        }
+    }
+
+    // not sure what they saw, so they go and have a look
+    for e in suspicious {
+        _ = gs.world.insert_one(e, Investigating { target: player_pos });
+    }
+}
+
+impl State {
+    /// Mark monsters in view that noticed something with `?` and those hunting the player with `!`
+    pub fn draw_awareness(&self, ctx: &mut BTerm) {
+        for (_, (pos, awareness)) in self.world.query::<(&Position, &Awareness)>().iter() {
+            if !self.map.visible[pos.into()] || pos.y == 0 {
+                continue;
+            }
+            let (glyph, color) = match awareness.level() {
+                AwarenessLevel::Unaware => continue,
+                AwarenessLevel::Suspicious => ('?', YELLOW),
+                AwarenessLevel::Alert => ('!', RED),
+            };
+            ctx.print_color(pos.x, pos.y - 1, color, BLACK, glyph);
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use bracket_lib::prelude::*;
+use hecs::Entity;
+
+use crate::{
+    combat::{CombatStats, SufferDamage},
+    damage::{Damage, DamageType},
+    monster::{Asleep, Monster},
+    movement,
+    noise::ALARM_LOUDNESS,
+    position::Position,
+    status::Confused,
+    Name, State, ViewShed,
+};
+
+const SEARCH_RADIUS: f32 = 3.0;
+const PERCEPTION_RADIUS: f32 = 2.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrapKind {
+    Spikes { damage: i32 },
+    Teleport,
+    Alarm,
+    ConfusionGas { turns: i32 },
+}
+
+impl TrapKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            TrapKind::Spikes { .. } => "Spike trap",
+            TrapKind::Teleport => "Teleport trap",
+            TrapKind::Alarm => "Alarm trap",
+            TrapKind::ConfusionGas { .. } => "Gas trap",
+        }
+    }
+
+    pub fn color(self) -> RGBA {
+        match self {
+            TrapKind::Spikes { .. } => RGBA::named(WHITE),
+            TrapKind::Teleport => RGBA::named(MAGENTA),
+            TrapKind::Alarm => RGBA::named(YELLOW),
+            TrapKind::ConfusionGas { .. } => RGBA::named(GREEN),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Trap {
+    pub kind: TrapKind,
+}
+
+impl Trap {
+    pub fn new(kind: TrapKind) -> Self {
+        Self { kind }
+    }
+}
+
+/// Not shown to the player until found
+#[derive(Debug)]
+pub struct Hidden;
+
+/// Fire the traps on the tile a creature just stepped onto
+pub fn entered(gs: &mut State, victim: Entity, pos: Position) {
+    if !gs.world.satisfies::<&CombatStats>(victim).unwrap_or(false) {
+        return;
+    }
+    let fired: Vec<(Entity, TrapKind)> = gs
+        .map
+        .index
+        .at(pos)
+        .iter()
+        .filter_map(|&e| Some((e, gs.world.get::<&Trap>(e).ok()?.kind)))
+        .collect();
+    for (trap_entity, kind) in fired {
+        trigger(gs, trap_entity, kind, pos, victim);
+    }
+}
+
+/// Monsters caught in a trap, or awake to see one go off, path around it from then on
+fn learn(gs: &mut State, trap_pos: Position, victim: Entity) {
+    let witnessed = gs.world.satisfies::<&Monster>(victim).unwrap_or(false)
+        || gs
+            .world
+            .query_mut::<&ViewShed>()
+            .with::<&Monster>()
+            .without::<&Asleep>()
+            .into_iter()
+            .any(|(_, fov)| fov.visible_tiles.contains(&trap_pos));
+    if witnessed {
+        gs.known_traps.insert(gs.map.to_idx(trap_pos));
+    }
+}
+
+fn trigger(gs: &mut State, trap: Entity, kind: TrapKind, trap_pos: Position, victim: Entity) {
+    learn(gs, trap_pos, victim);
+    let seen = gs.map.visible[trap_pos.into()];
+    if seen {
+        _ = gs.world.remove_one::<Hidden>(trap);
+    }
+    let name = gs
+        .world
+        .get::<&Name>(victim)
+        .map(|name| name.to_string())
+        .unwrap_or_default();
+
+    match kind {
+        TrapKind::Spikes { damage } => {
+            if seen {
+                gs.msg_log.push(format!("{name} is impaled by spikes!"));
+            }
+            SufferDamage::add_damage(
+                &mut gs.world,
+                victim,
+                Damage::new(damage, DamageType::Piercing).by(trap),
+            );
+        }
+        TrapKind::Teleport => {
+            if seen {
+                gs.msg_log.push(format!("{name} vanishes!"));
+            }
+            let dst = gs.map.random_floor(&mut gs.rng);
+            movement::relocate(gs, victim, dst);
+        }
+        TrapKind::Alarm => {
+            gs.msg_log.push("A loud alarm rings out!".into());
+            gs.make_noise(trap_pos, ALARM_LOUDNESS);
+        }
+        TrapKind::ConfusionGas { turns } => {
+            if seen {
+                gs.msg_log
+                    .push(format!("{name} is caught in a cloud of gas!"));
+            }
+            _ = gs.world.insert_one(victim, Confused { turns });
+        }
+    }
+}
+
+/// Give the player a chance of noticing hidden traps nearby
+pub fn perceive(gs: &mut State) {
+    let player_pos = *gs.world.query_one_mut::<&Position>(gs.player).unwrap();
+    let spotted = gs.find_hidden(player_pos, PERCEPTION_RADIUS, 5);
+    gs.reveal(spotted);
+}
+
+impl State {
+    /// Spend a turn looking for hidden traps around the player
+    pub fn search(&mut self) -> bool {
+        let player_pos = *self.world.query_one_mut::<&Position>(self.player).unwrap();
+        let found = self.find_hidden(player_pos, SEARCH_RADIUS, 2);
+        if found.is_empty() {
+            self.msg_log.push("You search but find nothing.".into());
+        }
+        self.reveal(found);
+        true
+    }
+
+    /// Hidden things in view within `radius` of `center`,
+    /// each one found with a chance of one in `odds`
+    fn find_hidden(&mut self, center: Position, radius: f32, odds: i32) -> Vec<Entity> {
+        let candidates: Vec<Entity> = self
+            .map
+            .index
+            .in_radius(center, radius)
+            .filter(|&(_, pos)| self.map.visible[pos.into()])
+            .filter(|&(e, _)| self.world.satisfies::<&Hidden>(e).unwrap_or(false))
+            .map(|(e, _)| e)
+            .collect();
+        candidates
+            .into_iter()
+            .filter(|_| self.rng.roll_dice(1, odds) == 1)
+            .collect()
+    }
+
+    fn reveal(&mut self, found: Vec<Entity>) {
+        for e in found {
+            if let Ok(trap) = self.world.get::<&Trap>(e) {
+                self.msg_log
+                    .push(format!("You spot a {}.", trap.kind.name().to_lowercase()));
+            }
+            _ = self.world.remove_one::<Hidden>(e);
+        }
+    }
+}
@@ -1,10 +1,23 @@
-use bracket_lib::terminal::{BTerm, BLACK, RED, WHITE, YELLOW};
+use bracket_lib::pathfinding::Algorithm2D;
+use bracket_lib::terminal::{BTerm, BLACK, BLUE, CYAN, GOLD, GREEN, GREY, RED, WHITE, YELLOW};
 
-use crate::{combat::CombatStats, CONSOLE_HEIGHT, CONSOLE_WIDTH, UI_HEIGHT};
+use crate::{
+    ability::{Abilities, Mana},
+    combat::CombatStats,
+    companion::Companion,
+    corpse::Contents,
+    hunger::Hunger,
+    position::Position,
+    room::Purse,
+    trap::Hidden,
+    Name, Renderable, CONSOLE_HEIGHT, CONSOLE_WIDTH, UI_HEIGHT,
+};
 
 impl super::State {
     pub fn draw_ui(&mut self, ctx: &mut BTerm) {
         const PADDING: i32 = 1;
+        // companions listed by name, the rest are only counted so the message log keeps room
+        const MAX_COMPANIONS: usize = 3;
 
         let mut y = CONSOLE_HEIGHT - UI_HEIGHT;
         ctx.set_active_console(0);
@@ -18,9 +31,102 @@ impl super::State {
             ctx.draw_bar_horizontal(15, y, 43, hp, max_hp, RED, BLACK);
         }
 
-        for msg in &self.msg_log[self.msg_log.len().saturating_sub(UI_HEIGHT as usize - 2)..] {
+        y += 1;
+        if let Ok(&Mana { max, current, .. }) = self.world.query_one_mut::<&Mana>(self.player) {
+            let mana = format!(" MP: {:2} / {:2} ", current, max);
+            ctx.print_color(PADDING, y, CYAN, BLACK, mana);
+            ctx.draw_bar_horizontal(15, y, 43, current, max, BLUE, BLACK);
+        }
+
+        let mut companions = 0;
+        let mut unlisted = 0;
+        for (_, (name, stats, companion)) in
+            self.world.query_mut::<(&Name, &CombatStats, &Companion)>()
+        {
+            if companions == MAX_COMPANIONS {
+                unlisted += 1;
+                continue;
+            }
+            y += 1;
+            companions += 1;
+            let status = format!(
+                " {name}: {:2} / {:2} ({})",
+                stats.hp, stats.max_hp, companion.command
+            );
+            ctx.print_color(PADDING, y, GREEN, BLACK, status);
+        }
+        if unlisted > 0 {
+            y += 1;
+            companions += 1;
+            ctx.print_color(PADDING, y, GREEN, BLACK, format!(" +{unlisted} more"));
+        }
+
+        if let Ok(hunger) = self.world.query_one_mut::<&Hunger>(self.player) {
+            let state = hunger.state();
+            let status = format!(" {state} ");
+            ctx.print_color(PADDING, CONSOLE_HEIGHT - 1, state.color(), BLACK, status);
+        }
+
+        if let Ok(purse) = self.world.query_one_mut::<&Purse>(self.player) {
+            let gold = format!(" Gold: {} ", purse.gold);
+            ctx.print_color(16, CONSOLE_HEIGHT - 1, GOLD, BLACK, gold);
+        }
+
+        // hotkeys of the abilities, greyed out while they cannot be used
+        if let Ok((abilities, mana)) = self
+            .world
+            .query_one_mut::<(&Abilities, Option<&Mana>)>(self.player)
+        {
+            let mut x = CONSOLE_WIDTH - 1 - 3 * abilities.0.len() as i32;
+            for (key, ability) in (1..).zip(&abilities.0) {
+                let color = if ability.ready(mana) { WHITE } else { GREY };
+                ctx.print_color(x, CONSOLE_HEIGHT - 1, color, BLACK, format!("[{key}]"));
+                x += 3;
+            }
+        }
+
+        let lines = (UI_HEIGHT as usize - 3).saturating_sub(companions);
+        for msg in &self.msg_log[self.msg_log.len().saturating_sub(lines)..] {
             y += 1;
             ctx.print(PADDING, y, msg);
         }
     }
+
+    /// Names of the things under the mouse cursor, or what is remembered there when out of sight
+    pub fn draw_tooltip(&self, ctx: &mut BTerm) {
+        let mouse = ctx.mouse_point();
+        let Ok(mouse_pos) = Position::try_from(mouse - self.camera_offset()) else {
+            return;
+        };
+        if !self.map.in_bounds(mouse_pos.into()) || !self.map.revealed[mouse_pos.into()] {
+            return;
+        }
+
+        let names: Vec<String> = if !self.map.visible[mouse_pos.into()] {
+            self.remembered_at(mouse_pos)
+        } else {
+            self.world
+                .query::<(&Position, &Name, Option<&Contents>)>()
+                .with::<&Renderable>()
+                .without::<&Hidden>()
+                .iter()
+                .filter(|(_, (pos, _, _))| **pos == mouse_pos)
+                .map(|(_, (_, name, contents))| match contents {
+                    Some(Contents(items)) if !items.is_empty() => {
+                        format!("{name} ({} items)", items.len())
+                    }
+                    _ => name.to_string(),
+                })
+                .collect()
+        };
+        let width = names.iter().map(|name| name.len()).max().unwrap_or(0) as i32;
+        let x = if mouse.x + width + 1 < CONSOLE_WIDTH {
+            mouse.x + 1
+        } else {
+            mouse.x - width
+        };
+        for (y, name) in (mouse.y..).zip(names) {
+            ctx.print_color(x, y, WHITE, BLACK, name);
+        }
+    }
 }
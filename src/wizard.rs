@@ -0,0 +1,345 @@
+use bracket_lib::prelude::*;
+use bracket_lib::terminal::console;
+use hecs::Entity;
+
+use crate::{
+    ability::Mana, combat::CombatStats, corpse::Contents, event, hunger::Hunger, map::TileType,
+    movement, populate, position::Position, room::Purse, spawn, BlocksTile, Options, Phase, State,
+    ViewShed, CONSOLE_WIDTH,
+};
+
+const HELP: &str = "Commands: reveal, god, spawn NAME [X Y], teleport [X Y], \
+                    set hp|maxhp|mana|gold|food N, regen SEED, overlay fov|blocked|ids [X Y]";
+
+/// Keys that type a letter into the console, in alphabetical order
+const LETTERS: [VirtualKeyCode; 26] = {
+    use VirtualKeyCode::*;
+    [
+        A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    ]
+};
+
+/// Keys that type a digit into the console, in order
+const DIGITS: [VirtualKeyCode; 10] = {
+    use VirtualKeyCode::*;
+    [Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9]
+};
+
+/// Debug tools available when the game is started with `--wizard`
+#[derive(Debug, Default)]
+pub struct Wizard {
+    /// The command being typed into the console
+    input: String,
+    /// Whether the player takes no damage
+    god: bool,
+    show_blocked: bool,
+    show_ids: bool,
+    /// Creature whose field of view is drawn
+    watched: Option<Entity>,
+    /// Commands from a script still to be run
+    script: Vec<String>,
+}
+
+impl Wizard {
+    /// Queue the commands of a script, one per line, skipping blank lines and `#` comments
+    pub fn load_script(&mut self, text: &str) {
+        self.script = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(String::from)
+            .collect();
+    }
+}
+
+/// The character a key types into the console
+fn typed_char(key: VirtualKeyCode, shift: bool) -> Option<char> {
+    if let Some(i) = LETTERS.iter().position(|&k| k == key) {
+        let c = (b'a' + i as u8) as char;
+        return Some(if shift { c.to_ascii_uppercase() } else { c });
+    }
+    if let Some(i) = DIGITS.iter().position(|&k| k == key) {
+        return Some((b'0' + i as u8) as char);
+    }
+    match key {
+        VirtualKeyCode::Space => Some(' '),
+        VirtualKeyCode::Minus => Some('-'),
+        VirtualKeyCode::Period => Some('.'),
+        _ => None,
+    }
+}
+
+/// Where a command applies: the coordinates it was given, or else the mouse cursor
+fn target(gs: &State, args: &[&str], cursor: Option<Position>) -> Result<Position, String> {
+    let pos = match args {
+        [x, y] => {
+            let (Ok(x), Ok(y)) = (x.parse::<u16>(), y.parse::<u16>()) else {
+                return Err(format!("Bad coordinates: {x} {y}."));
+            };
+            Position { x, y }
+        }
+        [] => cursor.ok_or("No position given.")?,
+        _ => return Err("Expected X Y.".into()),
+    };
+    if !gs.map.in_bounds(pos.into()) {
+        return Err(format!("{},{} is off the map.", pos.x, pos.y));
+    }
+    if gs.map.tiles[pos.into()] != TileType::Floor {
+        return Err(format!("{},{} is a wall.", pos.x, pos.y));
+    }
+    Ok(pos)
+}
+
+/// Give the player a stat's new value, raising its maximum if need be
+fn set_stat(gs: &mut State, stat: &str, value: i32) -> Result<(), String> {
+    let player = gs.player;
+    let set = match stat {
+        "hp" => gs.world.get::<&mut CombatStats>(player).map(|mut stats| {
+            stats.hp = value;
+            stats.max_hp = stats.max_hp.max(value);
+        }),
+        "maxhp" => gs.world.get::<&mut CombatStats>(player).map(|mut stats| {
+            stats.max_hp = value;
+            stats.hp = stats.hp.min(value);
+        }),
+        "mana" => gs.world.get::<&mut Mana>(player).map(|mut mana| {
+            mana.current = value;
+            mana.max = mana.max.max(value);
+        }),
+        "gold" => gs
+            .world
+            .get::<&mut Purse>(player)
+            .map(|mut purse| purse.gold = value),
+        "food" => gs
+            .world
+            .get::<&mut Hunger>(player)
+            .map(|mut hunger| hunger.food = value),
+        _ => return Err(format!("Unknown stat: {stat}.")),
+    };
+    set.map_err(|_| format!("The player has no {stat}."))
+}
+
+/// Throw the level away and generate a new one from `seed`, wizard settings included
+fn regenerate(gs: &mut State, seed: u64) {
+    let mut wizard = gs.wizard.take();
+    if let Some(wizard) = &mut wizard {
+        wizard.watched = None;
+    }
+    let options = Options {
+        level: None,
+        ..gs.options.clone()
+    };
+    *gs = State::new(seed, options);
+    populate(gs);
+    // later commands of a script need the new level indexed already
+    gs.index_entities();
+    gs.wizard = wizard;
+    gs.phase = Phase::Startup;
+}
+
+/// Carry out one console command, returning what to tell the player
+fn execute(gs: &mut State, line: &str, cursor: Option<Position>) -> Result<String, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let Some((&command, args)) = words.split_first() else {
+        return Err(HELP.into());
+    };
+    let Some(wizard) = &mut gs.wizard else {
+        return Err("Wizard mode is off.".into());
+    };
+    match (command, args) {
+        ("help", _) => Ok(HELP.into()),
+        ("reveal", []) => {
+            gs.map.revealed.fill(true);
+            Ok("The whole level is revealed.".into())
+        }
+        ("god", []) => {
+            wizard.god = !wizard.god;
+            Ok(format!(
+                "God mode {}.",
+                if wizard.god { "on" } else { "off" }
+            ))
+        }
+        ("spawn", [name, at @ ..]) => {
+            let pos = target(gs, at, cursor)?;
+            let e = spawn::named(&mut gs.world, &mut gs.rng, name, pos)
+                .ok_or_else(|| format!("Nothing is called {name}."))?;
+            if gs.map.index.is_blocked(pos) && gs.world.satisfies::<&BlocksTile>(e).unwrap_or(false)
+            {
+                // take it back, along with anything it was carrying
+                let contents = gs
+                    .world
+                    .get::<&Contents>(e)
+                    .map(|contents| contents.0.clone())
+                    .unwrap_or_default();
+                for spawned in contents.into_iter().chain([e]) {
+                    _ = gs.world.despawn(spawned);
+                }
+                return Err(format!("Something stands on {},{}.", pos.x, pos.y));
+            }
+            gs.place(e);
+            Ok(format!("Spawned {name} at {},{}.", pos.x, pos.y))
+        }
+        ("teleport", at) => {
+            let pos = target(gs, at, cursor)?;
+            if gs.map.index.is_blocked(pos) {
+                return Err(format!("Something stands on {},{}.", pos.x, pos.y));
+            }
+            let player = gs.player;
+            movement::relocate(gs, player, pos);
+            Ok(format!("Teleported to {},{}.", pos.x, pos.y))
+        }
+        ("set", [stat, value]) => {
+            let value = value
+                .parse()
+                .map_err(|_| format!("Not a number: {value}"))?;
+            set_stat(gs, stat, value)?;
+            Ok(format!("Set {stat} to {value}."))
+        }
+        ("regen", [seed]) => {
+            let seed = u64::from_str_radix(seed, 16).map_err(|_| format!("Not a seed: {seed}"))?;
+            regenerate(gs, seed);
+            Ok(format!("Regenerated the level from seed {seed:016x}."))
+        }
+        ("overlay", ["blocked"]) => {
+            wizard.show_blocked = !wizard.show_blocked;
+            Ok("Toggled the blocked tiles overlay.".into())
+        }
+        ("overlay", ["ids"]) => {
+            wizard.show_ids = !wizard.show_ids;
+            Ok("Toggled the entity IDs overlay.".into())
+        }
+        ("overlay", ["fov", at @ ..]) => {
+            if at.is_empty() && wizard.watched.is_some() {
+                wizard.watched = None;
+                return Ok("Field of view overlay off.".into());
+            }
+            let pos = target(gs, at, cursor)?;
+            let watched = gs
+                .map
+                .index
+                .at(pos)
+                .iter()
+                .copied()
+                .filter(|&e| e != gs.player)
+                .find(|&e| gs.world.satisfies::<&ViewShed>(e).unwrap_or(false))
+                .ok_or_else(|| format!("Nothing sees from {},{}.", pos.x, pos.y))?;
+            if let Some(wizard) = &mut gs.wizard {
+                wizard.watched = Some(watched);
+            }
+            Ok(format!(
+                "Showing the field of view from {},{}.",
+                pos.x, pos.y
+            ))
+        }
+        _ => Err(format!("Unknown command: {line}. {HELP}")),
+    }
+}
+
+/// Run a command and tell the player how it went
+fn run_command(gs: &mut State, line: &str, cursor: Option<Position>) {
+    let msg = match execute(gs, line, cursor) {
+        Ok(msg) => msg,
+        Err(err) => {
+            console::log(format!("[wizard] {line}: {err}"));
+            err
+        }
+    };
+    gs.msg_log.push(msg);
+}
+
+/// Run the commands of the script given on the command line, once the level is indexed
+pub fn run_script(gs: &mut State) {
+    let Some(wizard) = &mut gs.wizard else {
+        return;
+    };
+    for line in std::mem::take(&mut wizard.script) {
+        run_command(gs, &line, None);
+    }
+}
+
+impl State {
+    /// Whether the player is spared all damage
+    pub fn god_mode(&self) -> bool {
+        self.wizard.as_ref().is_some_and(|wizard| wizard.god)
+    }
+
+    pub fn open_console(&mut self) -> bool {
+        if let Some(wizard) = &mut self.wizard {
+            wizard.input.clear();
+            self.phase = Phase::Console;
+        }
+        false
+    }
+
+    /// Type into the console, returns whether it was closed
+    pub fn console_input(&mut self, ctx: &mut BTerm) -> bool {
+        let Some(key) = ctx.key.take() else {
+            return false;
+        };
+        let Some(wizard) = &mut self.wizard else {
+            return true;
+        };
+        match key {
+            VirtualKeyCode::Escape => self.phase = Phase::Rendering,
+            VirtualKeyCode::Return => {
+                let line = std::mem::take(&mut wizard.input);
+                self.phase = Phase::Rendering;
+                let cursor = Position::try_from(ctx.mouse_point() - self.camera_offset()).ok();
+                run_command(self, &line, cursor);
+                self.compute_visibility();
+                event::dispatch(self);
+            }
+            VirtualKeyCode::Back => {
+                wizard.input.pop();
+                return false;
+            }
+            _ => {
+                if let Some(c) = typed_char(key, ctx.shift) {
+                    wizard.input.push(c);
+                }
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn draw_console(&self, ctx: &mut BTerm) {
+        ctx.set_active_console(1);
+        ctx.cls();
+        self.draw_wizard_overlays(ctx);
+        if let Some(wizard) = &self.wizard {
+            let line = format!("> {}_", wizard.input);
+            let width = CONSOLE_WIDTH as usize;
+            ctx.print_color(0, 0, YELLOW, BLACK, format!("{line:<width$}"));
+        }
+    }
+
+    /// The debug overlays turned on from the console
+    pub fn draw_wizard_overlays(&self, ctx: &mut BTerm) {
+        let Some(wizard) = &self.wizard else {
+            return;
+        };
+        if let Some(fov) = wizard
+            .watched
+            .and_then(|e| self.world.get::<&ViewShed>(e).ok())
+        {
+            for &pos in &fov.visible_tiles {
+                let p = Point::from(pos);
+                ctx.set(p.x, p.y, MAGENTA, BLACK, to_cp437('·'));
+            }
+        }
+        if wizard.show_blocked {
+            for (idx, tile) in self.map.tiles.iter().enumerate() {
+                let p = Point::from(self.map.to_pos(idx));
+                if *tile == TileType::Floor && !self.map.is_passable(p) {
+                    ctx.set(p.x, p.y, RED, BLACK, to_cp437('X'));
+                }
+            }
+        }
+        if wizard.show_ids {
+            for (e, pos) in self.world.query::<&Position>().iter() {
+                ctx.print_color(pos.x, pos.y, YELLOW, BLACK, e.id());
+            }
+        }
+    }
+}